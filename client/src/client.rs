@@ -1,7 +1,13 @@
 use crate::{config::ConfigSource, error::ConfigError};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde_json::Value;
-use std::collections::HashMap;
+
+/// Credentials sent with every request to the config server.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
 
 pub struct ConfigClient {
     client: Client,
@@ -9,6 +15,7 @@ pub struct ConfigClient {
     application: String,
     profile: String,
     label: String,
+    credentials: Option<Credentials>,
 }
 
 impl ConfigClient {
@@ -19,6 +26,22 @@ impl ConfigClient {
             application,
             profile,
             label,
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.credentials {
+            Some(Credentials::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+            None => request,
         }
     }
 
@@ -34,8 +57,7 @@ impl ConfigClient {
         tracing::debug!("Fetching config from: {}", url);
 
         let response = self
-            .client
-            .get(&url)
+            .authorize(self.client.get(&url))
             .send()
             .await
             .map_err(ConfigError::Request)?;
@@ -56,8 +78,7 @@ impl ConfigClient {
         let request_body = serde_json::json!({ "value": value });
 
         let response = self
-            .client
-            .post(&url)
+            .authorize(self.client.post(&url))
             .json(&request_body)
             .send()
             .await
@@ -81,8 +102,7 @@ impl ConfigClient {
         let request_body = serde_json::json!({ "encrypted": encrypted });
 
         let response = self
-            .client
-            .post(&url)
+            .authorize(self.client.post(&url))
             .json(&request_body)
             .send()
             .await
@@ -104,8 +124,7 @@ impl ConfigClient {
         let url = format!("{}/refresh", self.server_url.trim_end_matches('/'));
 
         let response = self
-            .client
-            .post(&url)
+            .authorize(self.client.post(&url))
             .send()
             .await
             .map_err(ConfigError::Request)?;
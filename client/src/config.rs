@@ -1,4 +1,4 @@
-use crate::{client::ConfigClient, error::ConfigError};
+use crate::client::{ConfigClient, Credentials};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -7,6 +7,7 @@ pub struct ConfigClientBuilder {
     application: Option<String>,
     profile: Option<String>,
     label: Option<String>,
+    credentials: Option<Credentials>,
 }
 
 impl ConfigClientBuilder {
@@ -16,6 +17,7 @@ impl ConfigClientBuilder {
             application: None,
             profile: None,
             label: None,
+            credentials: None,
         }
     }
 
@@ -34,20 +36,40 @@ impl ConfigClientBuilder {
         self
     }
 
+    /// Sets the Git label. Slashes, as in `feature/new-db`, are escaped as
+    /// `(_)` so the label fits in a single path segment.
     pub fn label(mut self, label: &str) -> Self {
-        self.label = Some(label.to_string());
+        self.label = Some(label.replace('/', "(_)"));
+        self
+    }
+
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some(Credentials::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    pub fn bearer_token(mut self, token: &str) -> Self {
+        self.credentials = Some(Credentials::Bearer(token.to_string()));
         self
     }
 
     pub fn build(self) -> ConfigClient {
-        ConfigClient::new(
+        let client = ConfigClient::new(
             self.server_url
                 .unwrap_or_else(|| "http://localhost:8888".to_string()),
             self.application
                 .unwrap_or_else(|| "application".to_string()),
             self.profile.unwrap_or_else(|| "default".to_string()),
             self.label.unwrap_or_else(|| "master".to_string()),
-        )
+        );
+
+        match self.credentials {
+            Some(credentials) => client.with_credentials(credentials),
+            None => client,
+        }
     }
 }
 
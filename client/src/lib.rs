@@ -2,7 +2,7 @@ pub mod client;
 pub mod config;
 pub mod error;
 
-pub use client::ConfigClient;
+pub use client::{ConfigClient, Credentials};
 pub use config::{ConfigClientBuilder, ConfigSource};
 pub use error::ConfigError;
 
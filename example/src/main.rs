@@ -80,9 +80,7 @@
 //     Ok(())
 // }
 
-use config_client::{
-    ConfigClientBuilder, get_all_config, get_config, get_config_or, init_config, print_all_config,
-};
+use config_client::{get_all_config, init_config, print_all_config};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -115,7 +113,7 @@ async fn main() -> anyhow::Result<()> {
 
     // Print environment variables that were set
     println!("\n🌍 Environment variables set from config:");
-    for (key, _) in &all_configs {
+    for key in all_configs.keys() {
         if let Ok(env_value) = std::env::var(key) {
             println!("ENV[{}] = {}", key, env_value);
         }
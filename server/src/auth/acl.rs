@@ -0,0 +1,80 @@
+use super::{Principal, ROLE_ADMIN};
+use crate::utils::pattern;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Name used for requests that carry no principal (authentication disabled).
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Grants a principal access to the applications, profiles and labels
+/// matching the given wildcard patterns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AclRule {
+    /// Principal name, or `*` for every principal
+    pub principal: String,
+    pub applications: Vec<String>,
+    #[serde(default = "match_all")]
+    pub profiles: Vec<String>,
+    #[serde(default = "match_all")]
+    pub labels: Vec<String>,
+}
+
+fn match_all() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AclFile {
+    #[serde(default)]
+    rules: Vec<AclRule>,
+}
+
+/// Access control list for configuration reads. An empty list allows every
+/// request; otherwise a request must match at least one rule. Admins bypass
+/// the list.
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    rules: Vec<AclRule>,
+}
+
+impl Acl {
+    pub fn new(rules: Vec<AclRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ACL file {}", path))?;
+        let file: AclFile =
+            serde_yaml::from_str(&content).with_context(|| format!("Invalid ACL file {}", path))?;
+        Ok(Self::new(file.rules))
+    }
+
+    pub fn is_allowed(
+        &self,
+        principal: Option<&Principal>,
+        application: &str,
+        profile: &str,
+        label: &str,
+    ) -> bool {
+        if self.rules.is_empty() || principal.is_some_and(|p| p.has_role(ROLE_ADMIN)) {
+            return true;
+        }
+
+        let name = principal.map(|p| p.name.as_str()).unwrap_or(ANONYMOUS);
+
+        self.rules.iter().any(|rule| {
+            (rule.principal == "*" || rule.principal == name)
+                && matches_any(&rule.applications, application)
+                && profile
+                    .split(',')
+                    .map(|p| p.trim())
+                    .all(|p| matches_any(&rule.profiles, p))
+                && matches_any(&rule.labels, label)
+        })
+    }
+}
+
+fn matches_any(patterns: &[String], value: &str) -> bool {
+    patterns.iter().any(|p| pattern::matches(p, value))
+}
@@ -0,0 +1,121 @@
+use super::Principal;
+use anyhow::{Context, Result};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtConfig {
+    /// Local JWKS file holding the issuer's public keys
+    pub jwks_path: String,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    /// Algorithm for keys without an `alg` member; otherwise derived from
+    /// the key type
+    pub algorithm: Option<Algorithm>,
+    /// Claim listing the caller's roles, either an array or a space-separated string
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+pub struct JwtValidator {
+    config: JwtConfig,
+    jwks: JwkSet,
+}
+
+impl JwtValidator {
+    pub fn new(config: &JwtConfig) -> Result<Self> {
+        let content = std::fs::read_to_string(&config.jwks_path)
+            .with_context(|| format!("Failed to read JWKS file {}", config.jwks_path))?;
+        let jwks: JwkSet = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid JWKS file {}", config.jwks_path))?;
+
+        Ok(Self {
+            config: config.clone(),
+            jwks,
+        })
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Principal> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        let jwk = match &header.kid {
+            Some(kid) => self.jwks.find(kid),
+            None if self.jwks.keys.len() == 1 => self.jwks.keys.first(),
+            None => None,
+        }
+        .context("No matching key in JWKS")?;
+
+        // Never trust the algorithm named by the token itself
+        let algorithm = self.key_algorithm(jwk)?;
+        if header.alg != algorithm {
+            anyhow::bail!(
+                "Token algorithm {:?} does not match key algorithm {:?}",
+                header.alg,
+                algorithm
+            );
+        }
+
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        let mut validation = Validation::new(algorithm);
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)?.claims;
+
+        let name = claims["sub"]
+            .as_str()
+            .context("Token has no subject")?
+            .to_string();
+        let roles = match &claims[&self.config.roles_claim] {
+            Value::Array(roles) => roles
+                .iter()
+                .filter_map(|r| r.as_str())
+                .map(|r| r.to_string())
+                .collect(),
+            Value::String(roles) => roles.split_whitespace().map(|r| r.to_string()).collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(Principal { name, roles })
+    }
+
+    /// Algorithm the key verifies: its `alg` member, the configured
+    /// `algorithm`, or the default for its key type.
+    fn key_algorithm(&self, jwk: &Jwk) -> Result<Algorithm> {
+        if let Some(key_algorithm) = jwk.common.key_algorithm {
+            return Algorithm::from_str(&key_algorithm.to_string())
+                .with_context(|| format!("Unsupported key algorithm {}", key_algorithm));
+        }
+        if let Some(algorithm) = self.config.algorithm {
+            return Ok(algorithm);
+        }
+
+        match &jwk.algorithm {
+            AlgorithmParameters::RSA(_) => Ok(Algorithm::RS256),
+            AlgorithmParameters::EllipticCurve(params) => match params.curve {
+                EllipticCurve::P256 => Ok(Algorithm::ES256),
+                EllipticCurve::P384 => Ok(Algorithm::ES384),
+                _ => anyhow::bail!("Unsupported curve {:?}", params.curve),
+            },
+            AlgorithmParameters::OctetKeyPair(_) => Ok(Algorithm::EdDSA),
+            AlgorithmParameters::OctetKey(_) => {
+                anyhow::bail!("Symmetric keys need an alg member or a configured algorithm")
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+pub mod acl;
+pub mod jwt;
+
+use crate::tls::ClientCertificate;
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+pub const ROLE_READ: &str = "read";
+pub const ROLE_WRITE: &str = "write";
+pub const ROLE_ADMIN: &str = "admin";
+
+/// The authenticated caller, stored in the request extensions by
+/// [`authenticate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub name: String,
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    /// Admins implicitly hold every other role.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role || r == ROLE_ADMIN)
+    }
+}
+
+/// Contents of the file referenced by `AUTH_CONFIG`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    #[serde(default)]
+    pub certificates: Vec<CertificateConfig>,
+    pub jwt: Option<jwt::JwtConfig>,
+    /// Used when no separate `ACL_CONFIG` file is configured
+    #[serde(default)]
+    pub acl: Vec<acl::AclRule>,
+}
+
+/// An HTTP Basic user. `password` holds a bcrypt hash.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserConfig {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// A static bearer token issued to a named client.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenConfig {
+    pub name: String,
+    pub token: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// An mTLS client identified by the common name of its certificate.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CertificateConfig {
+    pub common_name: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+pub struct Authenticator {
+    config: Option<AuthConfig>,
+    jwt: Option<jwt::JwtValidator>,
+}
+
+impl Authenticator {
+    /// Loads the authentication settings. Without a config file every request
+    /// is allowed through, matching the server's historical behavior.
+    pub fn from_file(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            tracing::warn!("AUTH_CONFIG is not set; authentication is disabled");
+            return Ok(Self::disabled());
+        };
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read auth config {}", path))?;
+        let config: AuthConfig = serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid auth config {}", path))?;
+
+        let jwt = config
+            .jwt
+            .as_ref()
+            .map(jwt::JwtValidator::new)
+            .transpose()?;
+
+        Ok(Self {
+            config: Some(config),
+            jwt,
+        })
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            config: None,
+            jwt: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// The access control list embedded in the auth config, if any.
+    pub fn acl(&self) -> acl::Acl {
+        acl::Acl::new(
+            self.config
+                .as_ref()
+                .map(|c| c.acl.clone())
+                .unwrap_or_default(),
+        )
+    }
+
+    async fn authenticate(
+        &self,
+        authorization: &str,
+        certificate: Option<&ClientCertificate>,
+    ) -> Option<Principal> {
+        let config = self.config.as_ref()?;
+
+        if authorization.is_empty() {
+            let common_name = certificate?.common_name.as_deref()?;
+            let entry = config
+                .certificates
+                .iter()
+                .find(|c| c.common_name == common_name)?;
+            return Some(Principal {
+                name: entry.common_name.clone(),
+                roles: entry.roles.clone(),
+            });
+        }
+
+        if let Some(credentials) = authorization.strip_prefix("Basic ") {
+            let decoded = general_purpose::STANDARD.decode(credentials.trim()).ok()?;
+            let decoded = String::from_utf8(decoded).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+
+            let user = config.users.iter().find(|u| u.username == username);
+            // Unknown users are checked against another user's hash, so the
+            // response time does not reveal which usernames exist
+            let hash = user.or(config.users.first())?.password.clone();
+            let password = password.to_string();
+
+            // bcrypt is deliberately slow, keep it off the async workers
+            let valid = tokio::task::spawn_blocking(move || {
+                bcrypt::verify(password, &hash).unwrap_or(false)
+            })
+            .await
+            .ok()?;
+
+            let user = user?;
+            return valid.then(|| Principal {
+                name: user.username.clone(),
+                roles: user.roles.clone(),
+            });
+        }
+
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            let token = token.trim();
+            let digest = Sha256::digest(token.as_bytes());
+
+            if let Some(entry) = config
+                .tokens
+                .iter()
+                .find(|t| Sha256::digest(t.token.as_bytes()) == digest)
+            {
+                return Some(Principal {
+                    name: entry.name.clone(),
+                    roles: entry.roles.clone(),
+                });
+            }
+
+            if let Some(jwt) = &self.jwt {
+                return jwt
+                    .validate(token)
+                    .inspect_err(|e| tracing::debug!("JWT rejected: {:#}", e))
+                    .ok();
+            }
+        }
+
+        None
+    }
+}
+
+/// Authenticates the request and stores the resulting [`Principal`] in its
+/// extensions. Requests without valid credentials are rejected with 401.
+pub async fn authenticate(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !authenticator.enabled() {
+        return next.run(request).await;
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let certificate = request.extensions().get::<ClientCertificate>().cloned();
+
+    match authenticator
+        .authenticate(&authorization, certificate.as_ref())
+        .await
+    {
+        Some(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        None => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"config-server\"")],
+        )
+            .into_response(),
+    }
+}
+
+pub async fn require_read(
+    State(authenticator): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_role(&authenticator, ROLE_READ, request, next).await
+}
+
+pub async fn require_write(
+    State(authenticator): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_role(&authenticator, ROLE_WRITE, request, next).await
+}
+
+pub async fn require_admin(
+    State(authenticator): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_role(&authenticator, ROLE_ADMIN, request, next).await
+}
+
+async fn require_role(
+    authenticator: &Authenticator,
+    role: &str,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !authenticator.enabled() {
+        return next.run(request).await;
+    }
+
+    match request.extensions().get::<Principal>() {
+        Some(principal) if principal.has_role(role) => next.run(request).await,
+        Some(principal) => {
+            tracing::warn!(
+                "Principal {} lacks role {} for {}",
+                principal.name,
+                role,
+                request.uri().path()
+            );
+            StatusCode::FORBIDDEN.into_response()
+        }
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
@@ -0,0 +1,28 @@
+use clap::Parser;
+use config_server::utils::encryption;
+
+/// Encrypts a value offline with the config server's RSA public key
+/// (as served by `GET /key`). Only the server can decrypt the result.
+#[derive(Parser)]
+#[command(name = "config-encrypt")]
+struct Args {
+    /// Path to the PEM-encoded public key
+    #[arg(long)]
+    public_key: String,
+
+    /// Value to encrypt
+    value: String,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let pem = std::fs::read_to_string(&args.public_key)?;
+    let public_key = encryption::parse_public_key(&pem)?;
+
+    println!(
+        "{}",
+        encryption::encrypt_with_public_key(&public_key, &args.value)?
+    );
+    Ok(())
+}
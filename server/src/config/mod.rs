@@ -1,4 +1,7 @@
 
 pub mod server;
 
-pub use server::ServerConfig;
+pub use server::{
+    DatabaseConfig, GitCredentials, GitOptions, ServerConfig, TlsConfig, VaultAuthentication,
+    VaultConfig,
+};
@@ -1,23 +1,298 @@
+use crate::utils::encryption::DEFAULT_KEY_ID;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 
+/// Key used when `ENCRYPT_KEY` is not set. Only accepted, implicitly or when
+/// set explicitly, together with `ALLOW_INSECURE_DEFAULT_KEY=true` for local
+/// development.
+pub const DEFAULT_ENCRYPT_KEY: &str = "default-secret-key-32-characters";
+
+/// Salt paired with the insecure default key. Real keys require `ENCRYPT_SALT`.
+const DEFAULT_ENCRYPT_SALT: &str = "deadbeef";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub server_port: u16,
     pub health_port: u16,
     pub metrics_port: u16,
     pub config_path: String,
+    /// Watch `config_path` and evict changed applications from the cache
+    pub config_watch_enabled: bool,
+    pub config_watch_debounce_ms: u64,
     pub git_uri: Option<String>,
-    pub git_username: Option<String>,
-    pub git_password: Option<String>,
-    pub encrypt_key: String,
+    pub git_credentials: GitCredentials,
+    pub git_options: GitOptions,
+    pub git_repos_config: Option<String>,
+    /// Seconds between background fetches of the Git repositories
+    pub git_refresh_interval: Option<u64>,
+    /// Secret of the `default` key, `None` when `ENCRYPT_KEY` is not set
+    pub encrypt_key: Option<String>,
+    pub encrypt_salt: String,
+    pub encrypt_keys: HashMap<String, String>,
+    pub encrypt_active_key: Option<String>,
+    pub encrypt_rsa_private_key: Option<String>,
+    pub encrypt_enabled: bool,
     pub default_label: String,
     pub search_locations: Vec<String>,
+    pub resolve_placeholders: bool,
+    pub auth_config: Option<String>,
+    pub acl_config: Option<String>,
+    pub monitor_secret: Option<String>,
+    pub vault: Option<VaultConfig>,
+    pub database: Option<DatabaseConfig>,
+    /// YAML file listing the backends of composite mode
+    pub composite_config: Option<String>,
+    pub tls: Option<TlsConfig>,
+}
+
+/// Certificate settings shared by the three listeners. Client certificates
+/// are only requested on the main port, and only when `client_ca_path` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    pub client_ca_path: Option<String>,
+    pub server_enabled: bool,
+    pub health_enabled: bool,
+    pub metrics_enabled: bool,
+}
+
+impl TlsConfig {
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let (cert_path, key_path) = match (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+            (Ok(cert), Ok(key)) => (cert, key),
+            (Err(_), Err(_)) => return Ok(None),
+            _ => anyhow::bail!("TLS_CERT and TLS_KEY must be set together"),
+        };
+
+        let flag =
+            |name: &str, default: bool| env::var(name).map(|v| v == "true").unwrap_or(default);
+
+        Ok(Some(Self {
+            cert_path,
+            key_path,
+            client_ca_path: env::var("TLS_CLIENT_CA").ok(),
+            server_enabled: flag("TLS_ENABLED", true),
+            health_enabled: flag("HEALTH_TLS_ENABLED", false),
+            metrics_enabled: flag("METRICS_TLS_ENABLED", false),
+        }))
+    }
+}
+
+/// How to authenticate against a Git remote. SSH keys are tried in order:
+/// in-memory key, key file, then ssh-agent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GitCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// PEM-encoded private key, e.g. a deploy key injected as a secret
+    pub ssh_private_key: Option<String>,
+    pub ssh_private_key_path: Option<String>,
+    pub ssh_passphrase: Option<String>,
+    #[serde(default)]
+    pub ssh_agent: bool,
+    /// OpenSSH known_hosts file used to verify the server's host key
+    pub known_hosts_path: Option<String>,
+    #[serde(default = "default_strict_host_key_checking")]
+    pub strict_host_key_checking: bool,
+}
+
+fn default_strict_host_key_checking() -> bool {
+    true
+}
+
+impl GitCredentials {
+    fn from_env() -> Self {
+        Self {
+            username: env::var("GIT_USERNAME").ok(),
+            password: env::var("GIT_PASSWORD").ok(),
+            ssh_private_key: env::var("GIT_SSH_PRIVATE_KEY").ok(),
+            ssh_private_key_path: env::var("GIT_SSH_PRIVATE_KEY_PATH").ok(),
+            ssh_passphrase: env::var("GIT_SSH_PASSPHRASE").ok(),
+            ssh_agent: env::var("GIT_SSH_USE_AGENT")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            known_hosts_path: env::var("GIT_SSH_KNOWN_HOSTS").ok(),
+            strict_host_key_checking: env::var("GIT_SSH_STRICT_HOST_KEY_CHECKING")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+        }
+    }
+}
+
+/// Where and how a Git remote is cloned.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GitOptions {
+    /// Clone directory, defaults to a directory under the system temp dir
+    pub basedir: Option<String>,
+    /// Limits clones and fetches to the given number of commits
+    pub clone_depth: Option<i32>,
+    /// Refreshes an existing clone on startup and resets local branches to
+    /// the remote on every fetch
+    #[serde(default)]
+    pub force_pull: bool,
+}
+
+impl GitOptions {
+    fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            basedir: env::var("GIT_BASEDIR").ok(),
+            clone_depth: env::var("GIT_CLONE_DEPTH")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?
+                .filter(|depth| *depth > 0),
+            force_pull: env::var("GIT_FORCE_PULL")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+        })
+    }
+}
+
+/// HashiCorp Vault KV version 2 secrets engine read for every application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultConfig {
+    pub address: String,
+    /// Mount path of the KV engine
+    #[serde(default = "default_vault_backend")]
+    pub backend: String,
+    /// Secret shared by all applications, like `application.yml`
+    #[serde(default = "default_vault_key")]
+    pub default_key: String,
+    /// Separates the application from the profile in secret paths
+    #[serde(default = "default_vault_profile_separator")]
+    pub profile_separator: String,
+    /// Enterprise namespace sent as `X-Vault-Namespace`
+    pub namespace: Option<String>,
+    pub authentication: VaultAuthentication,
+    /// Fail config reads while Vault is unavailable; when unset they are
+    /// served without secrets. Composite backends use their own `fail_fast`.
+    #[serde(skip)]
+    pub fail_fast: bool,
+}
+
+fn default_vault_backend() -> String {
+    "secret".to_string()
+}
+
+fn default_vault_key() -> String {
+    "application".to_string()
+}
+
+fn default_vault_profile_separator() -> String {
+    ",".to_string()
+}
+
+/// How the server obtains a Vault token. AppRole and Kubernetes log in
+/// against the auth method mounted at `path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum VaultAuthentication {
+    Token {
+        token: String,
+    },
+    #[serde(rename = "approle")]
+    AppRole {
+        role_id: String,
+        secret_id: Option<String>,
+        path: String,
+    },
+    Kubernetes {
+        role: String,
+        /// Service account token presented as the login JWT
+        token_path: String,
+        path: String,
+    },
+}
+
+impl VaultConfig {
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(address) = env::var("VAULT_ADDR") else {
+            return Ok(None);
+        };
+
+        let var =
+            |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.to_string());
+        let required = |name: &str| {
+            env::var(name).map_err(|_| {
+                anyhow::anyhow!(
+                    "{} is required by the configured Vault authentication",
+                    name
+                )
+            })
+        };
+
+        let authentication = match var("VAULT_AUTHENTICATION", "token").to_lowercase().as_str() {
+            "token" => VaultAuthentication::Token {
+                token: required("VAULT_TOKEN")?,
+            },
+            "approle" => VaultAuthentication::AppRole {
+                role_id: required("VAULT_APP_ROLE_ID")?,
+                secret_id: env::var("VAULT_APP_ROLE_SECRET_ID").ok(),
+                path: var("VAULT_APP_ROLE_PATH", "approle"),
+            },
+            "kubernetes" => VaultAuthentication::Kubernetes {
+                role: required("VAULT_KUBERNETES_ROLE")?,
+                token_path: var(
+                    "VAULT_KUBERNETES_TOKEN_PATH",
+                    "/var/run/secrets/kubernetes.io/serviceaccount/token",
+                ),
+                path: var("VAULT_KUBERNETES_PATH", "kubernetes"),
+            },
+            other => anyhow::bail!("Unsupported VAULT_AUTHENTICATION: {}", other),
+        };
+
+        Ok(Some(Self {
+            address,
+            backend: var("VAULT_BACKEND", &default_vault_backend()),
+            default_key: var("VAULT_DEFAULT_KEY", &default_vault_key()),
+            profile_separator: var(
+                "VAULT_PROFILE_SEPARATOR",
+                &default_vault_profile_separator(),
+            ),
+            namespace: env::var("VAULT_NAMESPACE").ok(),
+            authentication,
+            fail_fast: var("VAULT_FAIL_FAST", "true") == "true",
+        }))
+    }
+}
+
+/// SQL table of properties, read with a query bound to the application,
+/// profile and label that returns `key` and `value` columns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    /// `sqlite:` followed by a file path, or a `postgres://` URL when built
+    /// with the `postgres` feature
+    pub url: String,
+    pub query: Option<String>,
+    /// PEM bundle verifying a PostgreSQL server that requires TLS, instead of
+    /// the platform's roots
+    pub ca_path: Option<String>,
 }
 
 impl ServerConfig {
     pub fn from_env() -> anyhow::Result<Self> {
-        Ok(Self {
+        let allow_insecure_key = env::var("ALLOW_INSECURE_DEFAULT_KEY")
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        let encrypt_key = match env::var("ENCRYPT_KEY") {
+            Ok(key) => Some(key),
+            Err(_) if allow_insecure_key => Some(DEFAULT_ENCRYPT_KEY.to_string()),
+            Err(_) => None,
+        };
+        let encrypt_keys = parse_key_list(&env::var("ENCRYPT_KEYS").unwrap_or_default())?;
+
+        // Every deployment must derive its keys with its own salt
+        let encrypt_salt = match env::var("ENCRYPT_SALT") {
+            Ok(salt) => salt,
+            Err(_) if env::var("ENCRYPT_KEY").is_ok() || !encrypt_keys.is_empty() => {
+                anyhow::bail!("ENCRYPT_SALT is required when ENCRYPT_KEY or ENCRYPT_KEYS is set")
+            }
+            Err(_) => DEFAULT_ENCRYPT_SALT.to_string(),
+        };
+
+        let config = Self {
             server_port: env::var("SERVER_PORT")
                 .unwrap_or_else(|_| "8888".to_string())
                 .parse()?,
@@ -29,18 +304,104 @@ impl ServerConfig {
                 .parse()?,
             config_path: env::var("CONFIG_PATH")
                 .unwrap_or_else(|_| "./configs".to_string()),
+            config_watch_enabled: env::var("CONFIG_WATCH_ENABLED")
+                .map(|v| v != "false")
+                .unwrap_or(true),
+            config_watch_debounce_ms: env::var("CONFIG_WATCH_DEBOUNCE_MS")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()?,
             git_uri: env::var("GIT_URI").ok(),
-            git_username: env::var("GIT_USERNAME").ok(),
-            git_password: env::var("GIT_PASSWORD").ok(),
-            encrypt_key: env::var("ENCRYPT_KEY")
-                .unwrap_or_else(|_| "default-secret-key-32-characters".to_string()),
+            git_credentials: GitCredentials::from_env(),
+            git_options: GitOptions::from_env()?,
+            git_repos_config: env::var("GIT_REPOS_CONFIG").ok(),
+            git_refresh_interval: env::var("GIT_REFRESH_INTERVAL")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?
+                .filter(|seconds| *seconds > 0),
+            encrypt_key,
+            encrypt_salt,
+            encrypt_keys,
+            encrypt_active_key: env::var("ENCRYPT_ACTIVE_KEY").ok(),
+            encrypt_rsa_private_key: env::var("ENCRYPT_RSA_PRIVATE_KEY").ok(),
+            encrypt_enabled: env::var("ENCRYPT_ENABLED")
+                .map(|v| v != "false")
+                .unwrap_or(true),
             default_label: env::var("DEFAULT_LABEL")
                 .unwrap_or_else(|_| "master".to_string()),
             search_locations: env::var("SEARCH_LOCATIONS")
-                .unwrap_or_else(|_| "classpath:/,classpath:/config/".to_string())
+                .unwrap_or_default()
                 .split(',')
                 .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
                 .collect(),
-        })
+            resolve_placeholders: env::var("RESOLVE_PLACEHOLDERS")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            auth_config: env::var("AUTH_CONFIG").ok(),
+            acl_config: env::var("ACL_CONFIG").ok(),
+            monitor_secret: env::var("MONITOR_SECRET").ok(),
+            vault: VaultConfig::from_env()?,
+            database: env::var("DATABASE_URL").ok().map(|url| DatabaseConfig {
+                url,
+                query: env::var("DATABASE_QUERY").ok(),
+                ca_path: env::var("DATABASE_CA").ok(),
+            }),
+            composite_config: env::var("COMPOSITE_CONFIG").ok(),
+            tls: TlsConfig::from_env()?,
+        };
+
+        // Only the default key falls back to the insecure secret, so a
+        // deployment using named or RSA keys does not need ENCRYPT_KEY
+        let default_key_active = config
+            .encrypt_active_key
+            .as_deref()
+            .is_none_or(|id| id == DEFAULT_KEY_ID);
+        if config.encrypt_key.is_none() && default_key_active {
+            anyhow::bail!(
+                "ENCRYPT_KEY is not set; refusing to start with the default encryption key \
+                 (set ALLOW_INSECURE_DEFAULT_KEY=true for local development)"
+            );
+        }
+
+        // The default secret is public, so setting it explicitly is no safer
+        let default_secret_set = config.encrypt_key.as_deref() == Some(DEFAULT_ENCRYPT_KEY)
+            || config.encrypt_keys.values().any(|key| key == DEFAULT_ENCRYPT_KEY);
+        if default_secret_set && !allow_insecure_key {
+            anyhow::bail!(
+                "ENCRYPT_KEY or ENCRYPT_KEYS uses the published default key; refusing to start \
+                 (set ALLOW_INSECURE_DEFAULT_KEY=true for local development)"
+            );
+        }
+
+        if config.composite_config.is_some()
+            && (config.git_uri.is_some()
+                || config.git_repos_config.is_some()
+                || config.vault.is_some()
+                || config.database.is_some())
+        {
+            anyhow::bail!(
+                "COMPOSITE_CONFIG replaces GIT_URI, GIT_REPOS_CONFIG, VAULT_ADDR and DATABASE_URL; \
+                 declare those backends in the composite file instead"
+            );
+        }
+
+        Ok(config)
     }
 }
+
+/// Parses `id=secret` pairs separated by commas, e.g.
+/// `prod-2025=old-secret,prod-2026=new-secret`.
+fn parse_key_list(value: &str) -> anyhow::Result<HashMap<String, String>> {
+    value
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .map(|(id, secret)| (id.trim().to_string(), secret.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid ENCRYPT_KEYS entry: expected id=secret"))
+        })
+        .collect()
+}
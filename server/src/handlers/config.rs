@@ -1,46 +1,325 @@
-use crate::{models::*, repository::ConfigRepository, utils::encryption};
+use crate::{
+    auth::{Principal, acl::Acl},
+    models::*,
+    repository::{
+        BackendUnavailable, ConfigRepository,
+        file::ResourceNotFound,
+        git::{LabelNotFound, unescape_label},
+        write::{InvalidWrite, VersionConflict, WriteMode},
+    },
+    utils::{document, name, placeholder::CircularReference},
+};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    Extension,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
 };
 use std::sync::Arc;
 
 pub async fn get_config(
     Path((application, profile, label)): Path<(String, String, String)>,
+    Query(query): Query<ConfigQuery>,
     State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
 ) -> Result<Json<ConfigResponse>, StatusCode> {
-    match repository.get_config(&application, &profile, &label).await {
+    authorize(&acl, principal.as_deref(), &application, &profile, &label)?;
+
+    match repository
+        .get_resolved_config(&application, &profile, &label, query.resolve_placeholders)
+        .await
+    {
         Ok(config) => {
             // Increment metrics
             metrics::counter!("config_requests_total", "application" => application.clone(), "profile" => profile.clone()).increment(1);
             Ok(Json(config))
         }
-        Err(_) => {
+        Err(err) => {
+            metrics::counter!("config_requests_failed_total", "application" => application, "profile" => profile).increment(1);
+            Err(error_status(&err))
+        }
+    }
+}
+
+/// Replaces the properties of `{application}-{profile}`.
+pub async fn put_config(
+    Path((application, profile, label)): Path<(String, String, String)>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<WriteRequest>,
+) -> Result<Json<WriteResponse>, StatusCode> {
+    write_config(
+        &repository,
+        &acl,
+        principal.as_deref(),
+        (&application, &profile, &label),
+        WriteMode::Replace,
+        &request,
+    )
+    .await
+}
+
+/// Sets individual properties of `{application}-{profile}`, deleting those
+/// set to `null`.
+pub async fn patch_config(
+    Path((application, profile, label)): Path<(String, String, String)>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<WriteRequest>,
+) -> Result<Json<WriteResponse>, StatusCode> {
+    write_config(
+        &repository,
+        &acl,
+        principal.as_deref(),
+        (&application, &profile, &label),
+        WriteMode::Merge,
+        &request,
+    )
+    .await
+}
+
+async fn write_config(
+    repository: &ConfigRepository,
+    acl: &Acl,
+    principal: Option<&Principal>,
+    (application, profile, label): (&str, &str, &str),
+    mode: WriteMode,
+    request: &WriteRequest,
+) -> Result<Json<WriteResponse>, StatusCode> {
+    authorize(acl, principal, application, profile, label)?;
+
+    // Commits are attributed to the authenticated principal, never to a
+    // name taken from the request body
+    let name = principal.map(|p| p.name.as_str()).unwrap_or("anonymous");
+    let author = CommitAuthor {
+        name: name.to_string(),
+        email: "config-server@localhost".to_string(),
+    };
+
+    match repository
+        .write_config(application, profile, label, mode, request, &author)
+        .await
+    {
+        Ok(response) => {
+            tracing::info!(
+                target: "config_server::audit",
+                principal = name,
+                application,
+                profile,
+                label,
+                keys = ?request.properties.keys().collect::<Vec<_>>(),
+                "Configuration written"
+            );
+            Ok(Json(response))
+        }
+        Err(err) => {
+            tracing::warn!(
+                "Failed to write {}/{}/{}: {:#}",
+                application,
+                profile,
+                label,
+                err
+            );
+            Err(error_status(&err))
+        }
+    }
+}
+
+pub async fn get_resource(
+    Path((application, profile, label, path)): Path<(String, String, String, String)>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, StatusCode> {
+    authorize(&acl, principal.as_deref(), &application, &profile, &label)?;
+
+    if path.split('/').any(|segment| segment == "..") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match repository
+        .get_resource(&application, &profile, &label, &path)
+        .await
+    {
+        Ok(content) => Ok((
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            content,
+        )
+            .into_response()),
+        Err(err) => Err(error_status(&err)),
+    }
+}
+
+pub async fn get_document(
+    Path(document): Path<String>,
+    Query(query): Query<ConfigQuery>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, StatusCode> {
+    render_document(
+        &repository,
+        &acl,
+        principal.as_deref(),
+        &document,
+        None,
+        &query,
+    )
+    .await
+}
+
+pub async fn get_labelled_document(
+    Path((label, document)): Path<(String, String)>,
+    Query(query): Query<ConfigQuery>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, StatusCode> {
+    render_document(
+        &repository,
+        &acl,
+        principal.as_deref(),
+        &document,
+        Some(&label),
+        &query,
+    )
+    .await
+}
+
+async fn render_document(
+    repository: &ConfigRepository,
+    acl: &Acl,
+    principal: Option<&Principal>,
+    document: &str,
+    label: Option<&str>,
+    query: &ConfigQuery,
+) -> Result<Response, StatusCode> {
+    let (application, profile, format) =
+        document::parse_document_name(document).ok_or(StatusCode::NOT_FOUND)?;
+    let label = label.unwrap_or_else(|| repository.default_label(&application, &profile));
+    authorize(acl, principal, &application, &profile, label)?;
+
+    match repository
+        .get_resolved_config(&application, &profile, label, query.resolve_placeholders)
+        .await
+    {
+        Ok(config) => {
+            metrics::counter!("config_requests_total", "application" => application, "profile" => profile).increment(1);
+            let body = document::render(&config.property_sources, format)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            Ok(([(header::CONTENT_TYPE, format.content_type())], body).into_response())
+        }
+        Err(err) => {
             metrics::counter!("config_requests_failed_total", "application" => application, "profile" => profile).increment(1);
-            Err(StatusCode::NOT_FOUND)
+            Err(error_status(&err))
         }
     }
 }
 
+/// Validates the names and checks the ACL, writing an audit log entry when
+/// access is denied. Every handler taking an application, profile and label
+/// runs this before touching a backend.
+pub(crate) fn authorize(
+    acl: &Acl,
+    principal: Option<&Principal>,
+    application: &str,
+    profile: &str,
+    label: &str,
+) -> Result<(), StatusCode> {
+    if let Err(e) = name::validate(application, profile, label) {
+        tracing::warn!("Rejected request: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Rules name branches as `feature/*`, however the label was escaped
+    if acl.is_allowed(principal, application, profile, &unescape_label(label)) {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        target: "config_server::audit",
+        principal = principal.map(|p| p.name.as_str()).unwrap_or("anonymous"),
+        application,
+        profile,
+        label,
+        "Access denied by ACL"
+    );
+    Err(StatusCode::FORBIDDEN)
+}
+
+/// Maps a repository error to the status code returned to clients. Failing
+/// backends are reported as unavailable rather than as missing configuration.
+pub(crate) fn error_status(err: &anyhow::Error) -> StatusCode {
+    if err.is::<CircularReference>() {
+        tracing::warn!("{}", err);
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else if err.is::<VersionConflict>() {
+        StatusCode::CONFLICT
+    } else if err.is::<InvalidWrite>() || err.is::<name::InvalidName>() {
+        StatusCode::BAD_REQUEST
+    } else if err.is::<LabelNotFound>() || err.is::<ResourceNotFound>() {
+        StatusCode::NOT_FOUND
+    } else if err.is::<BackendUnavailable>() {
+        tracing::error!("{:#}", err);
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        tracing::error!("{:#}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
 pub async fn encrypt_value(
+    State(repository): State<Arc<ConfigRepository>>,
     Json(request): Json<EncryptRequest>,
 ) -> Result<Json<EncryptResponse>, StatusCode> {
-    match encryption::encrypt(&request.value) {
+    match repository.keyring().encrypt(&request.value) {
         Ok(encrypted) => Ok(Json(EncryptResponse { encrypted })),
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
 pub async fn decrypt_value(
+    State(repository): State<Arc<ConfigRepository>>,
     Json(request): Json<DecryptRequest>,
 ) -> Result<Json<DecryptResponse>, StatusCode> {
-    match encryption::decrypt(&request.encrypted) {
+    match repository.keyring().decrypt(&request.encrypted) {
         Ok(decrypted) => Ok(Json(DecryptResponse { decrypted })),
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
+/// Publishes the server's RSA public key so values can be encrypted offline.
+pub async fn public_key(
+    State(repository): State<Arc<ConfigRepository>>,
+) -> Result<String, StatusCode> {
+    let asymmetric = repository
+        .keyring()
+        .asymmetric_key()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    asymmetric
+        .public_key_pem()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Re-encrypts every `{cipher}` value in the posted YAML, properties or JSON
+/// document with the active key, for bulk key rotation.
+pub async fn reencrypt_document(
+    State(repository): State<Arc<ConfigRepository>>,
+    document: String,
+) -> Result<String, StatusCode> {
+    repository
+        .keyring()
+        .rotate_document(&document)
+        .map_err(|e| {
+            tracing::warn!("Failed to re-encrypt document: {:#}", e);
+            StatusCode::BAD_REQUEST
+        })
+}
+
 pub async fn refresh_configs(
     State(repository): State<Arc<ConfigRepository>>,
 ) -> Result<&'static str, StatusCode> {
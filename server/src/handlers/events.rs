@@ -0,0 +1,131 @@
+use super::config::{authorize, error_status};
+use crate::{
+    auth::{Principal, acl::Acl},
+    models::*,
+    repository::ConfigRepository,
+    utils::document,
+};
+use axum::{
+    Extension,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::stream;
+use serde_json::Value;
+use std::{collections::BTreeMap, convert::Infallible, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+struct Subscription {
+    repository: Arc<ConfigRepository>,
+    application: String,
+    profile: String,
+    label: String,
+    resolve_placeholders: Option<bool>,
+    receiver: broadcast::Receiver<()>,
+    properties: BTreeMap<String, Value>,
+}
+
+impl Subscription {
+    /// Waits until a reload yields different properties and returns the
+    /// corresponding event, or `None` once the repository is gone.
+    async fn next_change(&mut self) -> Option<ConfigChangeEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(()) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return None,
+            }
+
+            let config = match self
+                .repository
+                .get_resolved_config(
+                    &self.application,
+                    &self.profile,
+                    &self.label,
+                    self.resolve_placeholders,
+                )
+                .await
+            {
+                Ok(config) => config,
+                Err(e) => {
+                    tracing::debug!("Failed to reload configuration for events: {:#}", e);
+                    continue;
+                }
+            };
+
+            let properties = document::merge_property_sources(&config.property_sources);
+            let changed_keys = changed_keys(&self.properties, &properties);
+            if changed_keys.is_empty() {
+                continue;
+            }
+            self.properties = properties;
+
+            return Some(ConfigChangeEvent {
+                name: config.name,
+                profiles: config.profiles,
+                label: config.label,
+                version: config.version,
+                changed_keys,
+            });
+        }
+    }
+}
+
+fn changed_keys(old: &BTreeMap<String, Value>, new: &BTreeMap<String, Value>) -> Vec<String> {
+    let mut keys: Vec<String> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(value))
+        .map(|(key, _)| key.clone())
+        .chain(old.keys().filter(|key| !new.contains_key(*key)).cloned())
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// Streams a `change` event whenever the resolved configuration of the
+/// application, profile and label changes after a refresh, webhook or poll.
+pub async fn config_events(
+    Path((application, profile, label)): Path<(String, String, String)>,
+    Query(query): Query<ConfigQuery>,
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(acl): Extension<Arc<Acl>>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, StatusCode> {
+    authorize(&acl, principal.as_deref(), &application, &profile, &label)?;
+
+    // Subscribe before loading the baseline so no change is missed
+    let receiver = repository.subscribe();
+    let config = repository
+        .get_resolved_config(&application, &profile, &label, query.resolve_placeholders)
+        .await
+        .map_err(|e| error_status(&e))?;
+
+    let subscription = Subscription {
+        repository,
+        application,
+        profile,
+        label,
+        resolve_placeholders: query.resolve_placeholders,
+        receiver,
+        properties: document::merge_property_sources(&config.property_sources),
+    };
+
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let change = subscription.next_change().await?;
+        let mut event = Event::default().event("change");
+        if let Some(version) = &change.version {
+            event = event.id(version);
+        }
+        let event = event
+            .json_data(&change)
+            .unwrap_or_else(|_| Event::default().event("change"));
+        Some((Ok::<_, Infallible>(event), subscription))
+    });
+
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
@@ -0,0 +1,61 @@
+use crate::{models::RouteQuery, repository::ConfigRepository, repository::routing::redact_uri};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Lists the configured Git repositories in matching order, and the backends
+/// of composite mode in precedence order. With
+/// `?application=...&profile=...` it also reports which repository serves
+/// that combination.
+pub async fn info(
+    State(repository): State<Arc<ConfigRepository>>,
+    Query(query): Query<RouteQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let repositories: Vec<Value> = repository
+        .repositories()
+        .iter()
+        .map(|routed| {
+            json!({
+                "name": routed.config.name,
+                "uri": redact_uri(&routed.config.uri),
+                "patterns": routed.config.patterns,
+                "defaultLabel": routed.config.default_label,
+            })
+        })
+        .collect();
+
+    let mut info = json!({
+        "repositories": repositories,
+        "default": repository.default_git_uri().map(redact_uri),
+    });
+
+    if let Some(composite) = repository.composite() {
+        info["composite"] = composite
+            .backends()
+            .iter()
+            .map(|backend| {
+                json!({
+                    "name": backend.config.name,
+                    "type": backend.config.source.kind(),
+                    "failFast": backend.config.fail_fast,
+                })
+            })
+            .collect();
+    }
+
+    if let Some(application) = &query.application {
+        let profile = query.profile.as_deref().unwrap_or("default");
+        info["route"] = json!({
+            "application": application,
+            "profile": profile,
+            "repository": repository.repository_name(application, profile),
+            "defaultLabel": repository.default_label(application, profile),
+        });
+    }
+
+    Ok(Json(info))
+}
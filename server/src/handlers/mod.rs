@@ -1,3 +1,6 @@
 pub mod config;
+pub mod events;
 pub mod health;
+pub mod info;
 pub mod metrics;
+pub mod monitor;
@@ -0,0 +1,71 @@
+use crate::{
+    repository::ConfigRepository,
+    utils::{
+        document,
+        webhook::{self, InvalidSignature, MonitorSecret},
+    },
+};
+use axum::{
+    Extension,
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct MonitorQuery {
+    /// `MONITOR_SECRET`, for Bitbucket Cloud which cannot sign deliveries
+    pub token: Option<String>,
+}
+
+/// Receives push webhooks from GitHub, GitLab, Gitea and Bitbucket, fetches
+/// the Git repositories and evicts the cached configuration of the
+/// applications whose files changed. Responds with the affected
+/// `application[:profile]` names, or `*` when the host does not list files.
+pub async fn monitor(
+    State(repository): State<Arc<ConfigRepository>>,
+    Extension(secret): Extension<MonitorSecret>,
+    Query(query): Query<MonitorQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    let event = match webhook::parse_push(&headers, &body, query.token.as_deref(), &secret.0) {
+        Ok(Some(event)) => event,
+        Ok(None) => return Ok(Json(Vec::new())),
+        Err(e) if e.is::<InvalidSignature>() => {
+            tracing::warn!("Rejected webhook: {}", e);
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Err(e) => {
+            tracing::debug!("Ignoring webhook: {:#}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    metrics::counter!("webhook_events_total", "host" => format!("{:?}", event.host)).increment(1);
+
+    let Some(paths) = event.paths else {
+        repository
+            .refresh()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok(Json(vec!["*".to_string()]));
+    };
+
+    repository.poll_changes().await;
+    let services = document::affected_services(&paths);
+    repository.evict_services(&services);
+
+    Ok(Json(
+        services
+            .into_iter()
+            .map(|(application, profile)| match profile {
+                Some(profile) => format!("{}:{}", application, profile),
+                None => application,
+            })
+            .collect(),
+    ))
+}
@@ -1,5 +1,7 @@
+pub mod auth;
 pub mod config;
 pub mod handlers;
 pub mod models;
 pub mod repository;
+pub mod tls;
 pub mod utils;
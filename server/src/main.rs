@@ -1,13 +1,16 @@
 use axum::{
-    Router,
-    routing::{get, post},
+    Extension, Router, middleware,
+    routing::{get, post, put},
 };
 use config_server::{
+    auth::{self, Authenticator, acl::Acl},
     config::ServerConfig,
-    handlers::{config, health, metrics},
-    repository::ConfigRepository,
+    handlers::{config, events, health, info, metrics, monitor},
+    repository::{ConfigRepository, watcher},
+    tls::{self, ReloadableTls},
+    utils::webhook::MonitorSecret,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -25,13 +28,106 @@ async fn main() -> anyhow::Result<()> {
     let config = ServerConfig::from_env()?;
     let repository = Arc::new(ConfigRepository::new(config.clone()).await?);
 
-    // Main application routes
-    let app = Router::new()
-        .route("/", get(|| async { "Config Server is running!" }))
+    if let Some(seconds) = config.git_refresh_interval {
+        tracing::info!("Fetching Git repositories every {}s", seconds);
+        tokio::spawn(
+            repository
+                .clone()
+                .poll_periodically(Duration::from_secs(seconds)),
+        );
+    }
+
+    // Keep the watcher alive for the lifetime of the server
+    let _config_watcher = if config.config_watch_enabled {
+        Some(watcher::watch(
+            repository.clone(),
+            &config.config_path,
+            Duration::from_millis(config.config_watch_debounce_ms),
+        )?)
+    } else {
+        None
+    };
+
+    let authenticator = Arc::new(Authenticator::from_file(config.auth_config.as_deref())?);
+    let acl = Arc::new(match &config.acl_config {
+        Some(path) => Acl::from_file(path)?,
+        None => authenticator.acl(),
+    });
+
+    // Configuration reads require the read role
+    let read_routes = Router::new()
         .route("/{application}/{profile}/{label}", get(config::get_config))
+        .route(
+            "/{application}/{profile}/{label}/events",
+            get(events::config_events),
+        )
+        .route(
+            "/{application}/{profile}/{label}/{*path}",
+            get(config::get_resource),
+        )
+        .route("/{document}", get(config::get_document))
+        .route("/{label}/{document}", get(config::get_labelled_document))
+        .layer(Extension(acl.clone()))
+        .route_layer(middleware::from_fn_with_state(
+            authenticator.clone(),
+            auth::require_read,
+        ));
+
+    // Configuration changes require the write role
+    let write_routes = Router::new()
+        .route(
+            "/{application}/{profile}/{label}",
+            put(config::put_config).patch(config::patch_config),
+        )
+        .layer(Extension(acl))
+        .route_layer(middleware::from_fn_with_state(
+            authenticator.clone(),
+            auth::require_write,
+        ));
+
+    // Encryption and repository management require the admin role
+    let admin_routes = Router::new()
         .route("/encrypt", post(config::encrypt_value))
         .route("/decrypt", post(config::decrypt_value))
         .route("/refresh", post(config::refresh_configs))
+        .route("/admin/reencrypt", post(config::reencrypt_document))
+        .route_layer(middleware::from_fn_with_state(
+            authenticator.clone(),
+            auth::require_admin,
+        ));
+
+    // Writes are attributed to the authenticated principal, so the routes
+    // only exist when authentication is enabled
+    let mut app = Router::new().merge(read_routes);
+    if authenticator.enabled() {
+        app = app.merge(write_routes);
+    } else {
+        tracing::info!("AUTH_CONFIG is not set; configuration writes are disabled");
+    }
+
+    // Main application routes
+    let mut app = app
+        .merge(admin_routes)
+        .layer(middleware::from_fn_with_state(
+            authenticator.clone(),
+            auth::authenticate,
+        ))
+        .route("/", get(|| async { "Config Server is running!" }));
+
+    // Git hosts authenticate webhooks with MONITOR_SECRET instead, so the
+    // endpoint only exists when a secret is configured
+    match &config.monitor_secret {
+        Some(secret) => {
+            app = app.route(
+                "/monitor",
+                post(monitor::monitor).layer(Extension(MonitorSecret(secret.clone()))),
+            );
+        }
+        None => tracing::info!("MONITOR_SECRET is not set; /monitor is disabled"),
+    }
+
+    let app = app
+        .route("/key", get(config::public_key))
         .layer(CorsLayer::permissive())
         .with_state(repository.clone());
 
@@ -40,6 +136,7 @@ async fn main() -> anyhow::Result<()> {
         .route("/health", get(health::health_check))
         .route("/health/live", get(health::liveness))
         .route("/health/ready", get(health::readiness))
+        .route("/actuator/info", get(info::info))
         .with_state(repository.clone());
 
     // Metrics routes (separate port)
@@ -50,6 +147,27 @@ async fn main() -> anyhow::Result<()> {
     // Initialize metrics
     metrics::init_metrics();
 
+    // TLS termination, with client certificates required on the main port
+    // when a client CA is configured
+    let mut tls_watchers = Vec::new();
+    let (main_tls, admin_tls) = match &config.tls {
+        Some(tls_config) => {
+            let main_tls = ReloadableTls::new(tls_config, true)?;
+            let admin_tls = ReloadableTls::new(tls_config, false)?;
+            tls_watchers.push(main_tls.watch()?);
+            tls_watchers.push(admin_tls.watch()?);
+            (
+                tls_config.server_enabled.then_some(main_tls),
+                Some(admin_tls),
+            )
+        }
+        None => (None, None),
+    };
+    let health_tls = admin_tls
+        .clone()
+        .filter(|_| config.tls.as_ref().is_some_and(|t| t.health_enabled));
+    let metrics_tls = admin_tls.filter(|_| config.tls.as_ref().is_some_and(|t| t.metrics_enabled));
+
     // Start servers
     let main_listener =
         tokio::net::TcpListener::bind(&format!("0.0.0.0:{}", config.server_port)).await?;
@@ -58,15 +176,19 @@ async fn main() -> anyhow::Result<()> {
     let metrics_listener =
         tokio::net::TcpListener::bind(&format!("0.0.0.0:{}", config.metrics_port)).await?;
 
-    tracing::info!("Config Server starting on port {}", config.server_port);
+    tracing::info!(
+        "Config Server starting on port {}{}",
+        config.server_port,
+        if main_tls.is_some() { " (TLS)" } else { "" }
+    );
     tracing::info!("Health checks available on port {}", config.health_port);
     tracing::info!("Metrics available on port {}", config.metrics_port);
 
     // Start all servers concurrently
     tokio::try_join!(
-        axum::serve(main_listener, app),
-        axum::serve(health_listener, health_app),
-        axum::serve(metrics_listener, metrics_app),
+        tls::serve(main_listener, app, main_tls),
+        tls::serve(health_listener, health_app, health_tls),
+        tls::serve(metrics_listener, metrics_app, metrics_tls),
     )?;
 
     Ok(())
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigResponse {
@@ -17,6 +17,12 @@ pub struct PropertySource {
     pub source: HashMap<String, serde_json::Value>,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConfigQuery {
+    #[serde(rename = "resolvePlaceholders")]
+    pub resolve_placeholders: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptRequest {
     pub value: String,
@@ -36,3 +42,49 @@ pub struct DecryptRequest {
 pub struct DecryptResponse {
     pub decrypted: String,
 }
+
+/// Sent to event stream subscribers when the resolved configuration of their
+/// application, profile and label changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigChangeEvent {
+    pub name: String,
+    pub profiles: Vec<String>,
+    pub label: String,
+    pub version: Option<String>,
+    /// Keys that were added, removed or given a new value
+    #[serde(rename = "changedKeys")]
+    pub changed_keys: Vec<String>,
+}
+
+/// Query parameters of the repository info endpoint.
+#[derive(Debug, Default, Deserialize)]
+pub struct RouteQuery {
+    pub application: Option<String>,
+    pub profile: Option<String>,
+}
+
+/// Body of `PUT` and `PATCH` requests. `PUT` replaces the properties of the
+/// document; `PATCH` sets the given keys and deletes those set to `null`.
+#[derive(Debug, Deserialize)]
+pub struct WriteRequest {
+    #[serde(default)]
+    pub properties: BTreeMap<String, serde_json::Value>,
+    /// Version the change is based on; stale writes are rejected
+    pub version: Option<String>,
+    /// Commit message for Git backends
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WriteResponse {
+    pub name: String,
+    pub profiles: Vec<String>,
+    pub label: String,
+    pub version: Option<String>,
+}
@@ -0,0 +1,250 @@
+use super::{
+    BackendUnavailable,
+    database::DatabaseRepository,
+    file::{FileRepository, ResourceNotFound},
+    git::GitRepository,
+    vault::VaultRepository,
+};
+use crate::{
+    config::{DatabaseConfig, GitCredentials, GitOptions, VaultConfig},
+    models::*,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// One entry of the composite backend list.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackendConfig {
+    pub name: String,
+    /// Fail the request when this backend errors, instead of skipping it
+    /// with a warning
+    #[serde(default = "default_fail_fast")]
+    pub fail_fast: bool,
+    #[serde(flatten)]
+    pub source: SourceConfig,
+}
+
+fn default_fail_fast() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SourceConfig {
+    Git {
+        uri: String,
+        #[serde(default)]
+        search_paths: Vec<String>,
+        #[serde(flatten)]
+        credentials: GitCredentials,
+        #[serde(flatten)]
+        options: GitOptions,
+    },
+    Native {
+        path: String,
+        #[serde(default)]
+        search_paths: Vec<String>,
+    },
+    Vault(VaultConfig),
+    Database(DatabaseConfig),
+}
+
+impl SourceConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SourceConfig::Git { .. } => "git",
+            SourceConfig::Native { .. } => "native",
+            SourceConfig::Vault(_) => "vault",
+            SourceConfig::Database(_) => "database",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CompositeFile {
+    #[serde(default)]
+    composite: Vec<BackendConfig>,
+}
+
+pub fn load_backends(path: &str) -> Result<Vec<BackendConfig>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read composite file {}", path))?;
+    let file: CompositeFile = serde_yaml::from_str(&content)
+        .with_context(|| format!("Invalid composite file {}", path))?;
+    Ok(file.composite)
+}
+
+pub enum Source {
+    Git(GitRepository),
+    Native(FileRepository),
+    Vault(VaultRepository),
+    Database(DatabaseRepository),
+}
+
+pub struct Backend {
+    pub config: BackendConfig,
+    pub source: Source,
+}
+
+impl Backend {
+    pub fn new(config: BackendConfig) -> Result<Self> {
+        let source = match &config.source {
+            SourceConfig::Git {
+                uri,
+                search_paths,
+                credentials,
+                options,
+            } => GitRepository::new(uri, credentials, options, search_paths).map(Source::Git),
+            SourceConfig::Native { path, search_paths } => {
+                FileRepository::new(path, search_paths).map(Source::Native)
+            }
+            SourceConfig::Vault(vault) => VaultRepository::new(vault).map(Source::Vault),
+            SourceConfig::Database(database) => {
+                DatabaseRepository::new(database).map(Source::Database)
+            }
+        }
+        .with_context(|| format!("Failed to open backend {}", config.name))?;
+
+        Ok(Self { config, source })
+    }
+
+    /// Property sources of this backend, lowest precedence first, with the
+    /// version it reports.
+    async fn load(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+    ) -> Result<(Vec<PropertySource>, Option<String>)> {
+        match &self.source {
+            Source::Git(git_repo) => {
+                let config = git_repo.get_config(application, profile, label).await?;
+                Ok((config.property_sources, config.version))
+            }
+            Source::Native(file_repo) => {
+                let config = file_repo.get_config(application, profile, label)?;
+                Ok((config.property_sources, config.version))
+            }
+            Source::Vault(vault_repo) => Ok((
+                vault_repo
+                    .get_property_sources(application, profile)
+                    .await?,
+                None,
+            )),
+            Source::Database(database_repo) => Ok((
+                database_repo
+                    .get_property_sources(application, profile, label)
+                    .await?,
+                None,
+            )),
+        }
+    }
+}
+
+/// An ordered list of backends that all contribute property sources. The
+/// first backend has the highest precedence.
+pub struct CompositeRepository {
+    backends: Vec<Backend>,
+}
+
+impl CompositeRepository {
+    pub fn new(configs: Vec<BackendConfig>) -> Result<Self> {
+        let backends = configs
+            .into_iter()
+            .map(Backend::new)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { backends })
+    }
+
+    pub fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+
+    /// Whether the merged configuration may be cached. Vault secrets and
+    /// database rows are read on every request so that changes are picked up.
+    pub fn is_cacheable(&self) -> bool {
+        !self
+            .backends
+            .iter()
+            .any(|backend| matches!(backend.source, Source::Vault(_) | Source::Database(_)))
+    }
+
+    /// Concatenates the property sources of every backend. The version is
+    /// taken from the highest-precedence backend that reports one.
+    pub async fn get_config(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+    ) -> Result<ConfigResponse> {
+        let mut property_sources = Vec::new();
+        let mut version = None;
+
+        // Sources are ordered from lowest to highest precedence
+        for backend in self.backends.iter().rev() {
+            match backend.load(application, profile, label).await {
+                Ok((sources, backend_version)) => {
+                    property_sources.extend(sources);
+                    version = backend_version.or(version);
+                }
+                Err(e) if backend.config.fail_fast => {
+                    return Err(e.context(BackendUnavailable(backend.config.name.clone())));
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping backend {}: {:#}", backend.config.name, e);
+                }
+            }
+        }
+
+        Ok(ConfigResponse {
+            name: application.to_string(),
+            profiles: profile.split(',').map(|s| s.trim().to_string()).collect(),
+            label: label.to_string(),
+            version,
+            property_sources,
+        })
+    }
+
+    /// Serves a resource from the first Git or native backend that has it.
+    pub async fn get_resource(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        path: &str,
+    ) -> Result<String> {
+        for backend in &self.backends {
+            let content = match &backend.source {
+                Source::Git(git_repo) => {
+                    git_repo
+                        .get_resource(application, profile, label, path)
+                        .await
+                }
+                Source::Native(file_repo) => {
+                    file_repo.get_resource(application, profile, label, path)
+                }
+                Source::Vault(_) | Source::Database(_) => continue,
+            };
+
+            match content {
+                Ok(content) => return Ok(content),
+                Err(e) => tracing::debug!(
+                    "Resource {} not served by backend {}: {:#}",
+                    path,
+                    backend.config.name,
+                    e
+                ),
+            }
+        }
+
+        Err(ResourceNotFound(path.to_string()).into())
+    }
+
+    /// Git backends with their names, for polling and cache eviction.
+    pub fn git_repositories(&self) -> impl Iterator<Item = (&str, &GitRepository)> {
+        self.backends.iter().filter_map(|b| match &b.source {
+            Source::Git(git_repo) => Some((b.config.name.as_str(), git_repo)),
+            _ => None,
+        })
+    }
+}
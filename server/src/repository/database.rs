@@ -0,0 +1,254 @@
+use crate::{config::DatabaseConfig, models::PropertySource};
+use anyhow::{Context, Result};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags, types::ValueRef};
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+
+const SQLITE_QUERY: &str =
+    "SELECT key, value FROM properties WHERE application = ?1 AND profile = ?2 AND label = ?3";
+
+#[cfg(feature = "postgres")]
+const POSTGRES_QUERY: &str =
+    "SELECT key, value FROM properties WHERE application = $1 AND profile = $2 AND label = $3";
+
+/// Profile of the rows that apply to every profile, like `{application}.yml`.
+const DEFAULT_PROFILE: &str = "default";
+
+/// Connections kept open per database.
+const POOL_SIZE: u32 = 8;
+
+/// Reads properties from a SQL table. The query is bound to the application,
+/// profile and label, and returns `key` and `value` columns.
+pub struct DatabaseRepository {
+    backend: Backend,
+    query: String,
+}
+
+enum Backend {
+    Sqlite(r2d2::Pool<SqliteConnectionManager>),
+    #[cfg(feature = "postgres")]
+    Postgres(deadpool_postgres::Pool),
+}
+
+impl DatabaseRepository {
+    pub fn new(config: &DatabaseConfig) -> Result<Self> {
+        let (backend, default_query) = if let Some(path) = config.url.strip_prefix("sqlite:") {
+            let path = Path::new(path.trim_start_matches("//"));
+            // Fail on startup rather than on the first request
+            open_sqlite(path)?;
+            let manager = SqliteConnectionManager::file(path).with_flags(sqlite_flags());
+            let pool = r2d2::Pool::builder()
+                .max_size(POOL_SIZE)
+                .build_unchecked(manager);
+            (Backend::Sqlite(pool), SQLITE_QUERY)
+        } else if config.url.starts_with("postgres://") || config.url.starts_with("postgresql://") {
+            #[cfg(feature = "postgres")]
+            {
+                (Backend::Postgres(postgres_pool(config)?), POSTGRES_QUERY)
+            }
+            #[cfg(not(feature = "postgres"))]
+            anyhow::bail!("PostgreSQL support requires building with the postgres feature")
+        } else {
+            anyhow::bail!("Unsupported database URL, expected sqlite: or postgres://")
+        };
+
+        Ok(Self {
+            backend,
+            query: config.query.as_deref().unwrap_or(default_query).to_string(),
+        })
+    }
+
+    /// Property sources in the same order as files: `application`, then
+    /// `application` for each profile, then the application itself. Rows with
+    /// the `default` profile apply to every profile.
+    pub async fn get_property_sources(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+    ) -> Result<Vec<PropertySource>> {
+        let profiles: Vec<&str> = profile
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty() && *p != DEFAULT_PROFILE)
+            .collect();
+
+        let mut names = vec!["application"];
+        if application != "application" {
+            names.push(application);
+        }
+
+        let lookups: Vec<(String, String)> = names
+            .iter()
+            .flat_map(|name| {
+                std::iter::once((name.to_string(), DEFAULT_PROFILE.to_string())).chain(
+                    profiles
+                        .iter()
+                        .map(move |p| (name.to_string(), p.to_string())),
+                )
+            })
+            .collect();
+
+        let rows = match &self.backend {
+            Backend::Sqlite(pool) => {
+                let (pool, query, lookups, label) = (
+                    pool.clone(),
+                    self.query.clone(),
+                    lookups.clone(),
+                    label.to_string(),
+                );
+                // rusqlite blocks, so queries run on the blocking pool
+                tokio::task::spawn_blocking(move || {
+                    let connection = pool.get()?;
+                    query_sqlite(&connection, &query, &lookups, &label)
+                })
+                .await??
+            }
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => self.query_postgres(pool, &lookups, label).await?,
+        };
+
+        Ok(lookups
+            .iter()
+            .zip(rows)
+            .filter(|(_, source)| !source.is_empty())
+            .map(|((name, profile), source)| PropertySource {
+                name: format!("database:{}-{}", name, profile),
+                source,
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "postgres")]
+    async fn query_postgres(
+        &self,
+        pool: &deadpool_postgres::Pool,
+        lookups: &[(String, String)],
+        label: &str,
+    ) -> Result<Vec<HashMap<String, Value>>> {
+        let client = pool
+            .get()
+            .await
+            .context("Failed to connect to PostgreSQL")?;
+        let statement = client.prepare_cached(&self.query).await?;
+
+        let mut sources = Vec::new();
+        for (application, profile) in lookups {
+            let rows = client
+                .query(&statement, &[application, profile, &label])
+                .await?;
+            let source = rows
+                .iter()
+                .map(|row| Ok((row.try_get(0)?, postgres_value(row)?)))
+                .collect::<Result<HashMap<String, Value>>>()?;
+            sources.push(source);
+        }
+        Ok(sources)
+    }
+}
+
+fn query_sqlite(
+    connection: &Connection,
+    query: &str,
+    lookups: &[(String, String)],
+    label: &str,
+) -> Result<Vec<HashMap<String, Value>>> {
+    let mut statement = connection.prepare_cached(query)?;
+
+    lookups
+        .iter()
+        .map(|(application, profile)| {
+            let mut rows = statement.query((application, profile, label))?;
+            let mut source = HashMap::new();
+            while let Some(row) = rows.next()? {
+                let key: String = row.get(0)?;
+                let value = match row.get_ref(1)? {
+                    ValueRef::Null => Value::Null,
+                    ValueRef::Integer(i) => Value::from(i),
+                    ValueRef::Real(f) => Value::from(f),
+                    ValueRef::Text(text) | ValueRef::Blob(text) => {
+                        Value::String(String::from_utf8_lossy(text).to_string())
+                    }
+                };
+                source.insert(key, value);
+            }
+            Ok(source)
+        })
+        .collect()
+}
+
+fn sqlite_flags() -> OpenFlags {
+    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX
+}
+
+fn open_sqlite(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(path, sqlite_flags())
+        .with_context(|| format!("Failed to open SQLite database {}", path.display()))
+}
+
+/// Pool of PostgreSQL connections. TLS is negotiated as the URL's `sslmode`
+/// asks, verifying the server against `ca_path` or the platform's roots.
+#[cfg(feature = "postgres")]
+fn postgres_pool(config: &DatabaseConfig) -> Result<deadpool_postgres::Pool> {
+    use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+
+    let pg_config: tokio_postgres::Config = config.url.parse().context("Invalid PostgreSQL URL")?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    match &config.ca_path {
+        Some(ca_path) => {
+            for cert in crate::tls::load_certs(ca_path)? {
+                roots.add(cert)?;
+            }
+        }
+        None => {
+            for cert in rustls_native_certs::load_native_certs().certs {
+                // Skip platform certificates rustls cannot parse
+                let _ = roots.add(cert);
+            }
+        }
+    }
+    let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+    let tls_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let manager = Manager::from_config(
+        pg_config,
+        tokio_postgres_rustls::MakeRustlsConnect::new(tls_config),
+        ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        },
+    );
+    Ok(Pool::builder(manager)
+        .max_size(POOL_SIZE as usize)
+        .build()?)
+}
+
+/// Converts the `value` column. Types without a JSON equivalent, such as
+/// `numeric` or `jsonb`, have to be cast to text in the query.
+#[cfg(feature = "postgres")]
+fn postgres_value(row: &tokio_postgres::Row) -> Result<Value> {
+    use tokio_postgres::types::Type;
+
+    let column = &row.columns()[1];
+    let value = match *column.type_() {
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => {
+            row.try_get::<_, Option<String>>(1)?.map(Value::String)
+        }
+        Type::BOOL => row.try_get::<_, Option<bool>>(1)?.map(Value::from),
+        Type::INT2 => row.try_get::<_, Option<i16>>(1)?.map(Value::from),
+        Type::INT4 => row.try_get::<_, Option<i32>>(1)?.map(Value::from),
+        Type::INT8 => row.try_get::<_, Option<i64>>(1)?.map(Value::from),
+        Type::FLOAT4 => row.try_get::<_, Option<f32>>(1)?.map(Value::from),
+        Type::FLOAT8 => row.try_get::<_, Option<f64>>(1)?.map(Value::from),
+        ref other => anyhow::bail!(
+            "Unsupported type {} of column {}; cast it to text in the query",
+            other,
+            column.name()
+        ),
+    };
+    Ok(value.unwrap_or(Value::Null))
+}
@@ -1,164 +1,428 @@
-use crate::models::*;
+use super::write::Edit;
+use crate::{
+    models::*,
+    utils::{document::DocumentFormat, name::InvalidName, properties},
+};
 use anyhow::Result;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::{fmt, fs};
+
+/// Returned when no backend holds a requested plain-text resource.
+#[derive(Debug)]
+pub struct ResourceNotFound(pub String);
+
+impl fmt::Display for ResourceNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No resource found for {}", self.0)
+    }
+}
+
+impl std::error::Error for ResourceNotFound {}
+
+/// Extensions of configuration documents, in the order they are looked up.
+pub const EXTENSIONS: [&str; 4] = ["yml", "yaml", "properties", "json"];
+
+/// Storage that configuration files are read from, either a directory on
+/// disk or the tree of a Git commit. Paths are relative to the storage root.
+pub trait ConfigStore {
+    /// Returns the content of the file, or `None` if it does not exist.
+    fn read_file(&self, path: &Path) -> Result<Option<String>>;
+
+    /// Name reported for the file in `PropertySource::name`.
+    fn source_name(&self, path: &Path) -> String;
+}
 
 pub struct FileRepository {
     base_path: String,
+    search_paths: Vec<String>,
 }
 
 impl FileRepository {
-    pub fn new(base_path: &str) -> Result<Self> {
+    pub fn new(base_path: &str, search_paths: &[String]) -> Result<Self> {
         fs::create_dir_all(base_path)?;
         Ok(Self {
             base_path: base_path.to_string(),
+            search_paths: search_paths.to_vec(),
         })
     }
 
+    /// Loads the configuration. The version is a digest of the loaded
+    /// properties, so writes can detect concurrent changes.
     pub fn get_config(
         &self,
         application: &str,
         profile: &str,
         label: &str,
     ) -> Result<ConfigResponse> {
-        let mut property_sources = Vec::new();
-        let profiles: Vec<String> = profile.split(',').map(|s| s.trim().to_string()).collect();
+        let mut config = load_config(self, &self.search_paths, application, profile, label)?;
+        config.version = Some(content_version(&config.property_sources)?);
+        Ok(config)
+    }
+
+    pub fn get_resource(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        path: &str,
+    ) -> Result<String> {
+        load_resource(self, &self.search_paths, application, profile, label, path)
+    }
+
+    /// Applies an edit to `{stem}.{ext}`, keeping the format of an existing
+    /// file. The file is looked up in the search directories of the
+    /// application, profile and label, the one taking precedence first; new
+    /// documents are written as YAML to the base directory.
+    pub fn write(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        stem: &str,
+        edit: &Edit,
+    ) -> Result<()> {
+        let (path, format, content) =
+            find_document(self, &self.search_paths, application, profile, label, stem)?;
+        let updated = edit.apply(content.as_deref(), format)?;
+
+        // Replace the file atomically so readers never see a partial write
+        let target = Path::new(&self.base_path).join(path);
+        let mut temp = target.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, updated)?;
+        fs::rename(&temp, &target)?;
+
+        tracing::info!("Wrote {}", target.display());
+        Ok(())
+    }
+}
 
-        // Load application.yml first (lowest priority)
-        if let Ok(source) = self.load_property_source("application", None) {
-            property_sources.push(source);
+impl ConfigStore for FileRepository {
+    fn read_file(&self, path: &Path) -> Result<Option<String>> {
+        let base = fs::canonicalize(&self.base_path)?;
+        let file_path = base.join(path);
+        if !file_path.is_file() {
+            return Ok(None);
         }
 
-        // Load profile-specific files
+        // Reject symlinks that escape the base directory
+        let resolved = fs::canonicalize(&file_path)?;
+        if !resolved.starts_with(&base) {
+            return Err(anyhow::anyhow!("Invalid path: {}", path.display()));
+        }
+
+        Ok(Some(fs::read_to_string(resolved)?))
+    }
+
+    fn source_name(&self, path: &Path) -> String {
+        Path::new(&self.base_path)
+            .join(path)
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Builds the configuration for an application from `store`, in order of
+/// increasing precedence: `application`, `application-{profile}`,
+/// `{application}` and `{application}-{profile}`. Each file is looked up in
+/// the root and then in every search path, later directories taking
+/// precedence.
+pub fn load_config(
+    store: &impl ConfigStore,
+    search_paths: &[String],
+    application: &str,
+    profile: &str,
+    label: &str,
+) -> Result<ConfigResponse> {
+    let mut property_sources = Vec::new();
+    let profiles: Vec<String> = profile.split(',').map(|s| s.trim().to_string()).collect();
+    let dirs = search_dirs(search_paths, application, &profiles, label);
+
+    // Load application.yml first (lowest priority)
+    property_sources.extend(load_property_sources(store, &dirs, "application", None));
+
+    // Load profile-specific files
+    for prof in &profiles {
+        property_sources.extend(load_property_sources(
+            store,
+            &dirs,
+            "application",
+            Some(prof),
+        ));
+    }
+
+    // Load application-specific files
+    if application != "application" {
+        property_sources.extend(load_property_sources(store, &dirs, application, None));
+
         for prof in &profiles {
-            if let Ok(source) = self.load_property_source("application", Some(prof)) {
-                property_sources.push(source);
-            }
+            property_sources.extend(load_property_sources(store, &dirs, application, Some(prof)));
         }
+    }
 
-        // Load application-specific files
-        if application != "application" {
-            if let Ok(source) = self.load_property_source(application, None) {
-                property_sources.push(source);
-            }
+    Ok(ConfigResponse {
+        name: application.to_string(),
+        profiles,
+        label: label.to_string(),
+        version: None,
+        property_sources,
+    })
+}
 
-            for prof in &profiles {
-                if let Ok(source) = self.load_property_source(application, Some(prof)) {
-                    property_sources.push(source);
-                }
+/// Reads a plain-text resource from `store`. Profile-specific variants
+/// (`nginx-prod.conf`) are preferred over the plain file, with the last
+/// listed profile taking precedence. Search paths are tried before the root,
+/// the last one first.
+pub fn load_resource(
+    store: &impl ConfigStore,
+    search_paths: &[String],
+    application: &str,
+    profile: &str,
+    label: &str,
+    path: &str,
+) -> Result<String> {
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(InvalidName(path.to_string()).into());
+    }
+
+    let profiles: Vec<String> = profile
+        .split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    let mut candidates: Vec<PathBuf> = profiles
+        .iter()
+        .rev()
+        .map(|p| profile_variant(relative, p))
+        .collect();
+    candidates.push(relative.to_path_buf());
+
+    for dir in search_dirs(search_paths, application, &profiles, label)
+        .iter()
+        .rev()
+    {
+        for candidate in &candidates {
+            if let Some(content) = store.read_file(&dir.join(candidate))? {
+                return Ok(content);
             }
         }
-
-        Ok(ConfigResponse {
-            name: application.to_string(),
-            profiles,
-            label: label.to_string(),
-            version: None,
-            property_sources,
-        })
     }
 
-    fn load_property_source(
-        &self,
-        application: &str,
-        profile: Option<&str>,
-    ) -> Result<PropertySource> {
-        let filename = match profile {
-            Some(p) => format!("{}-{}", application, p),
-            None => application.to_string(),
+    Err(ResourceNotFound(path.to_string()).into())
+}
+
+/// Expands the `{application}`, `{profile}` and `{label}` placeholders in the
+/// search paths. The root is always searched first; a path containing
+/// `{profile}` yields one directory per active profile.
+fn search_dirs(
+    search_paths: &[String],
+    application: &str,
+    profiles: &[String],
+    label: &str,
+) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::new()];
+
+    for search_path in search_paths {
+        let search_path = search_path
+            .trim()
+            .trim_matches('/')
+            .replace("{application}", application)
+            .replace("{label}", label);
+
+        let expanded: Vec<String> = if search_path.contains("{profile}") {
+            profiles
+                .iter()
+                .map(|p| search_path.replace("{profile}", p))
+                .collect()
+        } else {
+            vec![search_path]
         };
 
-        // Try different file extensions
-        for ext in &["yml", "yaml", "properties", "json"] {
-            let file_path = Path::new(&self.base_path).join(format!("{}.{}", filename, ext));
-
-            if file_path.exists() {
-                let content = fs::read_to_string(&file_path)?;
-                let source = match *ext {
-                    "yml" | "yaml" => self.parse_yaml(&content)?,
-                    "json" => self.parse_json(&content)?,
-                    "properties" => self.parse_properties(&content)?,
-                    _ => HashMap::new(),
-                };
+        for dir in expanded.into_iter().map(PathBuf::from) {
+            let is_relative = dir.components().all(|c| matches!(c, Component::Normal(_)));
+            if is_relative && !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+
+    dirs
+}
+
+/// Finds the document a write of `{stem}` updates: the existing file in the
+/// search directory taking precedence for the application, profile and label,
+/// or a new YAML file in the root. Returns its path, format and content.
+pub(crate) fn find_document(
+    store: &impl ConfigStore,
+    search_paths: &[String],
+    application: &str,
+    profile: &str,
+    label: &str,
+    stem: &str,
+) -> Result<(PathBuf, DocumentFormat, Option<String>)> {
+    let profiles = [profile.trim().to_string()];
+    let dirs = search_dirs(search_paths, application, &profiles, label);
 
-                return Ok(PropertySource {
-                    name: file_path.to_string_lossy().to_string(),
-                    source,
-                });
+    for dir in dirs.iter().rev() {
+        for ext in EXTENSIONS {
+            let path = dir.join(format!("{}.{}", stem, ext));
+            if let Some(content) = store.read_file(&path)? {
+                let format = DocumentFormat::from_extension(ext)
+                    .ok_or_else(|| anyhow::anyhow!("Unsupported extension {}", ext))?;
+                return Ok((path, format, Some(content)));
             }
         }
+    }
+
+    Ok((
+        PathBuf::from(format!("{}.yml", stem)),
+        DocumentFormat::Yaml,
+        None,
+    ))
+}
+
+/// Loads `{application}[-{profile}]` from every search directory holding it.
+fn load_property_sources(
+    store: &impl ConfigStore,
+    dirs: &[PathBuf],
+    application: &str,
+    profile: Option<&str>,
+) -> Vec<PropertySource> {
+    dirs.iter()
+        .filter_map(|dir| load_property_source(store, dir, application, profile).ok())
+        .collect()
+}
+
+fn load_property_source(
+    store: &impl ConfigStore,
+    dir: &Path,
+    application: &str,
+    profile: Option<&str>,
+) -> Result<PropertySource> {
+    let filename = match profile {
+        Some(p) => format!("{}-{}", application, p),
+        None => application.to_string(),
+    };
 
-        Err(anyhow::anyhow!(
-            "No configuration file found for {}",
-            filename
-        ))
+    // Try different file extensions
+    for ext in EXTENSIONS {
+        let file_path = dir.join(format!("{}.{}", filename, ext));
+
+        if let Some(content) = store.read_file(&file_path)? {
+            let source = match DocumentFormat::from_extension(ext) {
+                Some(format) => parse_content(format, &content)?,
+                None => HashMap::new(),
+            };
+
+            return Ok(PropertySource {
+                name: store.source_name(&file_path),
+                source,
+            });
+        }
     }
 
-    fn parse_yaml(&self, content: &str) -> Result<HashMap<String, Value>> {
-        let yaml_value: serde_yaml::Value = serde_yaml::from_str(content)?;
-        let json_value = serde_json::to_value(yaml_value)?;
-        self.flatten_json(json_value, String::new())
+    Err(anyhow::anyhow!(
+        "No configuration file found for {}",
+        filename
+    ))
+}
+
+/// Parses a document into flattened properties.
+pub fn parse_content(format: DocumentFormat, content: &str) -> Result<HashMap<String, Value>> {
+    match format {
+        DocumentFormat::Yaml => parse_yaml(content),
+        DocumentFormat::Json => parse_json(content),
+        DocumentFormat::Properties => parse_properties(content),
     }
+}
 
-    fn parse_json(&self, content: &str) -> Result<HashMap<String, Value>> {
-        let json_value: Value = serde_json::from_str(content)?;
-        self.flatten_json(json_value, String::new())
+/// Digest identifying the content of the property sources.
+fn content_version(property_sources: &[PropertySource]) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for source in property_sources {
+        let sorted: BTreeMap<_, _> = source.source.iter().collect();
+        hasher.update(source.name.as_bytes());
+        hasher.update(serde_json::to_vec(&sorted)?);
     }
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    fn parse_properties(&self, content: &str) -> Result<HashMap<String, Value>> {
-        let mut map = HashMap::new();
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-            if let Some((key, value)) = line.split_once('=') {
-                map.insert(
-                    key.trim().to_string(),
-                    Value::String(value.trim().to_string()),
-                );
-            }
-        }
-        Ok(map)
-    }
-
-    fn flatten_json(&self, value: Value, prefix: String) -> Result<HashMap<String, Value>> {
-        let mut map = HashMap::new();
-
-        match value {
-            Value::Object(obj) => {
-                for (key, val) in obj {
-                    let new_key = if prefix.is_empty() {
-                        key
-                    } else {
-                        format!("{}.{}", prefix, key)
-                    };
-
-                    match val {
-                        Value::Object(_) | Value::Array(_) => {
-                            let nested = self.flatten_json(val, new_key)?;
-                            map.extend(nested);
-                        }
-                        _ => {
-                            map.insert(new_key, val);
-                        }
+fn parse_yaml(content: &str) -> Result<HashMap<String, Value>> {
+    let yaml_value: serde_yaml::Value = serde_yaml::from_str(content)?;
+    let json_value = serde_json::to_value(yaml_value)?;
+    flatten_json(json_value, String::new())
+}
+
+fn parse_json(content: &str) -> Result<HashMap<String, Value>> {
+    let json_value: Value = serde_json::from_str(content)?;
+    flatten_json(json_value, String::new())
+}
+
+fn parse_properties(content: &str) -> Result<HashMap<String, Value>> {
+    Ok(properties::lines(content)
+        .into_iter()
+        .filter_map(|line| line.entry)
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect())
+}
+
+/// Flattens nested objects and arrays into `a.b[0].c` style keys.
+pub fn flatten_json(value: Value, prefix: String) -> Result<HashMap<String, Value>> {
+    let mut map = HashMap::new();
+
+    match value {
+        Value::Object(obj) => {
+            for (key, val) in obj {
+                let new_key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+
+                match val {
+                    Value::Object(_) | Value::Array(_) => {
+                        let nested = flatten_json(val, new_key)?;
+                        map.extend(nested);
+                    }
+                    _ => {
+                        map.insert(new_key, val);
                     }
                 }
             }
-            Value::Array(arr) => {
-                for (i, val) in arr.into_iter().enumerate() {
-                    let new_key = format!("{}[{}]", prefix, i);
-                    let nested = self.flatten_json(val, new_key)?;
-                    map.extend(nested);
-                }
-            }
-            _ => {
-                map.insert(prefix, value);
+        }
+        Value::Array(arr) => {
+            for (i, val) in arr.into_iter().enumerate() {
+                let new_key = format!("{}[{}]", prefix, i);
+                let nested = flatten_json(val, new_key)?;
+                map.extend(nested);
             }
         }
-
-        Ok(map)
+        _ => {
+            map.insert(prefix, value);
+        }
     }
+
+    Ok(map)
+}
+
+fn profile_variant(path: &Path, profile: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let filename = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, profile, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, profile),
+    };
+
+    path.with_file_name(filename)
 }
@@ -1,100 +1,896 @@
-use super::file::FileRepository;
-use crate::models::*;
-use anyhow::Result;
-use git2::{Cred, FetchOptions, RemoteCallbacks, Repository};
-use std::path::Path;
+use super::{
+    file::{self, ConfigStore},
+    known_hosts::{HostKeyStatus, KnownHosts},
+    write::{Edit, InvalidWrite, VersionConflict},
+};
+use crate::{
+    config::{GitCredentials, GitOptions},
+    models::*,
+};
+use anyhow::{Context, Result};
+use git2::{
+    BranchType, CertificateCheckStatus, Commit, Cred, CredentialType, ErrorCode, FetchOptions,
+    ObjectType, Oid, PushOptions, RemoteCallbacks, Repository, Signature, Tree,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
 
+/// Returned when a label matches no branch, tag or commit in the repository.
+#[derive(Debug)]
+pub struct LabelNotFound(pub String);
+
+impl fmt::Display for LabelNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown label: {}", self.0)
+    }
+}
+
+impl std::error::Error for LabelNotFound {}
+
+/// A branch or tag whose commit changed during a fetch.
+#[derive(Debug, Clone)]
+pub struct LabelChange {
+    pub label: String,
+    pub old: Option<Oid>,
+    pub new: Option<Oid>,
+    /// Files added, modified or deleted between the two commits
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct GitRepository {
-    repo_path: String,
+    repo_path: PathBuf,
     git_uri: String,
-    username: Option<String>,
-    password: Option<String>,
-    file_repo: FileRepository,
+    credentials: GitCredentials,
+    options: GitOptions,
+    search_paths: Vec<String>,
 }
 
 impl GitRepository {
-    pub fn new(git_uri: &str, username: Option<&str>, password: Option<&str>) -> Result<Self> {
-        let repo_path = "./git-config-repo";
+    pub fn new(
+        git_uri: &str,
+        credentials: &GitCredentials,
+        options: &GitOptions,
+        search_paths: &[String],
+    ) -> Result<Self> {
+        let repo_path = match &options.basedir {
+            Some(dir) => PathBuf::from(dir),
+            None => default_basedir(git_uri),
+        };
 
-        // Clone or open repository
-        let _repo = if Path::new(repo_path).exists() {
-            Repository::open(repo_path)?
-        } else {
-            let mut callbacks = RemoteCallbacks::new();
+        let repository = Self {
+            repo_path,
+            git_uri: git_uri.to_string(),
+            credentials: credentials.clone(),
+            options: options.clone(),
+            search_paths: search_paths.to_vec(),
+        };
+        repository.open_or_clone()?;
+
+        Ok(repository)
+    }
 
-            if let (Some(user), Some(pass)) = (username, password) {
-                callbacks.credentials(|_url, _username_from_url, _allowed_types| {
-                    Cred::userpass_plaintext(user, pass)
-                });
+    /// Reuses an existing clone when it is healthy and points at `git_uri`,
+    /// otherwise clones afresh.
+    fn open_or_clone(&self) -> Result<()> {
+        if self.repo_path.exists() {
+            match self.verify_clone() {
+                Ok(()) => {
+                    tracing::info!("Using existing clone at {}", self.repo_path.display());
+                    // Bring a stale clone up to date before serving from it
+                    if self.options.force_pull {
+                        self.fetch()?;
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!("Recloning into {}: {:#}", self.repo_path.display(), e);
+                    self.remove_clone()?;
+                }
             }
+        }
 
-            let mut fetch_options = FetchOptions::new();
-            fetch_options.remote_callbacks(callbacks);
+        tracing::info!("Cloning {} into {}", self.git_uri, self.repo_path.display());
 
-            let mut builder = git2::build::RepoBuilder::new();
-            builder.fetch_options(fetch_options);
-            builder.clone(git_uri, Path::new(repo_path))?
-        };
+        let mut fetch_options = fetch_options(&self.credentials, &self.git_uri)?;
+        if let Some(depth) = self.options.clone_depth {
+            fetch_options.depth(depth);
+        }
 
-        let file_repo = FileRepository::new(repo_path)?;
+        // Files are read from the object database, so no working tree is needed
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .fetch_options(fetch_options)
+            .clone(&self.git_uri, &self.repo_path)?;
 
-        Ok(Self {
-            repo_path: repo_path.to_string(),
-            git_uri: git_uri.to_string(),
-            username: username.map(|s| s.to_string()),
-            password: password.map(|s| s.to_string()),
-            file_repo,
-        })
+        Ok(())
+    }
+
+    fn verify_clone(&self) -> Result<()> {
+        let repo = Repository::open(&self.repo_path)?;
+        let remote = repo.find_remote("origin")?;
+        if remote.url() != Some(self.git_uri.as_str()) {
+            anyhow::bail!(
+                "existing clone points at {}",
+                remote.url().unwrap_or("an unknown remote")
+            );
+        }
+
+        repo.head()?.peel_to_commit()?;
+        Ok(())
     }
 
+    /// Deletes a stale clone. Directories that do not look like a Git
+    /// repository are left alone, in case the base directory was misconfigured.
+    fn remove_clone(&self) -> Result<()> {
+        let is_empty = std::fs::read_dir(&self.repo_path)?.next().is_none();
+        let is_repository =
+            self.repo_path.join(".git").exists() || self.repo_path.join("HEAD").exists();
+        if !is_empty && !is_repository {
+            anyhow::bail!(
+                "{} exists and is not a Git repository; refusing to delete it",
+                self.repo_path.display()
+            );
+        }
+
+        std::fs::remove_dir_all(&self.repo_path)?;
+        Ok(())
+    }
+
+    /// Reads the configuration straight from the tree of the commit the label
+    /// points at, so concurrent requests for different labels never touch a
+    /// shared working directory.
     pub async fn get_config(
         &self,
         application: &str,
         profile: &str,
         label: &str,
     ) -> Result<ConfigResponse> {
-        // Ensure we're on the correct branch/label
-        self.checkout_label(label)?;
-        self.file_repo.get_config(application, profile, label)
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = resolve_label(&repo, label)?;
+        let store = TreeStore::new(&repo, &commit, &self.git_uri)?;
+
+        let mut config =
+            file::load_config(&store, &self.search_paths, application, profile, label)?;
+        config.version = Some(commit.id().to_string());
+        Ok(config)
     }
 
-    pub async fn pull(&self) -> Result<()> {
+    pub async fn get_resource(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        path: &str,
+    ) -> Result<String> {
         let repo = Repository::open(&self.repo_path)?;
-        let mut remote = repo.find_remote("origin")?;
+        let commit = resolve_label(&repo, label)?;
+        let store = TreeStore::new(&repo, &commit, &self.git_uri)?;
+        file::load_resource(
+            &store,
+            &self.search_paths,
+            application,
+            profile,
+            label,
+            path,
+        )
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.git_uri
+    }
+
+    /// Commits an edit of `{stem}.{ext}` to the label's branch and pushes it.
+    /// The document is looked up in the search paths like a read of the
+    /// application and profile would. The branch must still be at
+    /// `expected_version` when given. Returns the new commit id. Like
+    /// fetches, the commit and push run on the blocking pool.
+    pub async fn write(
+        &self,
+        (application, profile, label): (&str, &str, &str),
+        expected_version: Option<&str>,
+        stem: &str,
+        edit: &Edit,
+        author: &CommitAuthor,
+        message: Option<&str>,
+    ) -> Result<String> {
+        let repository = self.clone();
+        let (application, profile, label, stem, edit, author) = (
+            application.to_string(),
+            profile.to_string(),
+            label.to_string(),
+            stem.to_string(),
+            edit.clone(),
+            author.clone(),
+        );
+        let expected_version = expected_version.map(str::to_string);
+        let message = message.map(str::to_string);
+        tokio::task::spawn_blocking(move || {
+            repository.commit_edit(
+                (&application, &profile, &label),
+                expected_version.as_deref(),
+                &stem,
+                &edit,
+                &author,
+                message.as_deref(),
+            )
+        })
+        .await?
+    }
+
+    fn commit_edit(
+        &self,
+        (application, profile, label): (&str, &str, &str),
+        expected_version: Option<&str>,
+        stem: &str,
+        edit: &Edit,
+        author: &CommitAuthor,
+        message: Option<&str>,
+    ) -> Result<String> {
+        let repo = Repository::open(&self.repo_path)?;
+        let branch = unescape_label(label);
+        let tracking = format!("refs/remotes/origin/{}", branch);
+        let parent = match repo.find_reference(&tracking) {
+            Ok(reference) => reference.peel_to_commit()?,
+            Err(_) => {
+                // Tags and commit ids exist but cannot be written to
+                resolve_label(&repo, label)?;
+                return Err(InvalidWrite(format!("label {} is not a branch", branch)).into());
+            }
+        };
 
-        let mut callbacks = RemoteCallbacks::new();
-        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
-            callbacks.credentials(|_url, _username_from_url, _allowed_types| {
-                Cred::userpass_plaintext(user, pass)
+        let current = parent.id().to_string();
+        if let Some(expected) = expected_version
+            && expected != current
+        {
+            return Err(VersionConflict {
+                expected: expected.to_string(),
+                actual: Some(current),
+            }
+            .into());
+        }
+
+        let store = TreeStore::new(&repo, &parent, &self.git_uri)?;
+        let (path, format, content) = file::find_document(
+            &store,
+            &self.search_paths,
+            application,
+            profile,
+            label,
+            stem,
+        )?;
+        let updated = edit.apply(content.as_deref(), format)?;
+
+        let tree = &store.tree;
+        let names: Vec<&str> = path.iter().filter_map(|name| name.to_str()).collect();
+        let blob = repo.blob(updated.as_bytes())?;
+        let new_tree = repo.find_tree(insert_blob(&repo, Some(tree), &names, blob)?)?;
+        if new_tree.id() == tree.id() {
+            return Ok(current);
+        }
+
+        let file_name = path.display();
+        let signature = Signature::now(&author.name, &author.email)?;
+        let message = message
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Update {}", file_name));
+        let commit = repo.commit(
+            None,
+            &signature,
+            &signature,
+            &message,
+            &new_tree,
+            &[&parent],
+        )?;
+
+        let local = format!("refs/heads/{}", branch);
+        repo.reference(&local, commit, true, "config-server write")?;
+        self.push(&repo, &local, &current)?;
+
+        // Serve the new commit right away instead of waiting for a fetch
+        repo.reference(&tracking, commit, true, "config-server write")?;
+        tracing::info!(
+            "Committed {} to {} of {} as {}",
+            file_name,
+            branch,
+            self.git_uri,
+            commit
+        );
+        Ok(commit.to_string())
+    }
+
+    /// Pushes a local branch to the branch of the same name. A rejected push
+    /// means the remote moved on from `base`.
+    fn push(&self, repo: &Repository, reference: &str, base: &str) -> Result<()> {
+        let rejected = RefCell::new(None);
+        let pushed = {
+            let mut callbacks = remote_callbacks(&self.credentials, &self.git_uri)?;
+            callbacks.push_update_reference(|_, status| {
+                if let Some(status) = status {
+                    *rejected.borrow_mut() = Some(status.to_string());
+                }
+                Ok(())
             });
+
+            let mut push_options = PushOptions::new();
+            push_options.remote_callbacks(callbacks);
+            repo.find_remote("origin")?.push(
+                &[format!("{}:{}", reference, reference)],
+                Some(&mut push_options),
+            )
+        };
+
+        // Local remotes report a non-fast-forward push as an error, network
+        // remotes through the callback
+        let rejection = match pushed {
+            Err(e) if e.code() == ErrorCode::NotFastForward => Some(e.message().to_string()),
+            Err(e) => return Err(e.into()),
+            Ok(()) => rejected.into_inner(),
+        };
+        if let Some(status) = rejection {
+            tracing::warn!(
+                "Push of {} to {} rejected: {}",
+                reference,
+                self.git_uri,
+                status
+            );
+            return Err(VersionConflict {
+                expected: base.to_string(),
+                actual: None,
+            }
+            .into());
         }
+        Ok(())
+    }
+
+    /// Fetches from the remote and reports the labels whose commit changed.
+    /// libgit2 blocks on the network, so the fetch runs on the blocking pool.
+    pub async fn pull(&self) -> Result<Vec<LabelChange>> {
+        tracing::debug!("Fetching configuration updates from {}", self.git_uri);
+        let repository = self.clone();
+        tokio::task::spawn_blocking(move || repository.fetch()).await?
+    }
 
-        let mut fetch_options = FetchOptions::new();
-        fetch_options.remote_callbacks(callbacks);
+    /// Fetches all branches and tags. With `force_pull`, local branches are
+    /// also reset to their remote-tracking branches, discarding any local
+    /// divergence.
+    fn fetch(&self) -> Result<Vec<LabelChange>> {
+        let repo = Repository::open(&self.repo_path)?;
+        let mut remote = repo.find_remote("origin")?;
+        let before = ref_tips(&repo)?;
 
+        let mut fetch_options = fetch_options(&self.credentials, &self.git_uri)?;
+        if let Some(depth) = self.options.clone_depth {
+            fetch_options.depth(depth);
+        }
         remote.fetch(
-            &["refs/heads/*:refs/remotes/origin/*"],
+            &[
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*",
+            ],
             Some(&mut fetch_options),
             None,
         )?;
 
-        Ok(())
+        if self.options.force_pull {
+            reset_local_branches(&repo)?;
+        }
+
+        let after = ref_tips(&repo)?;
+        let mut changes = Vec::new();
+        for label in before
+            .keys()
+            .chain(after.keys().filter(|l| !before.contains_key(*l)))
+        {
+            let (old, new) = (before.get(label).copied(), after.get(label).copied());
+            if old == new {
+                continue;
+            }
+
+            let paths = changed_paths(&repo, old, new)?;
+            tracing::info!(
+                "Label {} of {} moved from {} to {}; changed files: {}",
+                label,
+                self.git_uri,
+                old.map(|id| id.to_string())
+                    .unwrap_or_else(|| "nothing".to_string()),
+                new.map(|id| id.to_string())
+                    .unwrap_or_else(|| "nothing".to_string()),
+                paths.join(", ")
+            );
+            changes.push(LabelChange {
+                label: label.clone(),
+                old,
+                new,
+                paths,
+            });
+        }
+
+        Ok(changes)
     }
+}
 
-    fn checkout_label(&self, label: &str) -> Result<()> {
-        let repo = Repository::open(&self.repo_path)?;
+/// Commit each fetched branch and tag points at, keyed by label.
+fn ref_tips(repo: &Repository) -> Result<HashMap<String, Oid>> {
+    let mut tips = HashMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        let Some(label) = name
+            .strip_prefix("refs/remotes/origin/")
+            .or_else(|| name.strip_prefix("refs/tags/"))
+        else {
+            continue;
+        };
+        if label == "HEAD" {
+            continue;
+        }
 
-        // Try to find the branch
-        let branch_name = format!("refs/remotes/origin/{}", label);
-        if let Ok(reference) = repo.find_reference(&branch_name) {
-            let commit = reference.peel_to_commit()?;
-            repo.set_head_detached(commit.id())?;
+        if let Ok(commit) = reference.peel_to_commit() {
+            tips.insert(label.to_string(), commit.id());
+        }
+    }
+    Ok(tips)
+}
+
+/// Paths that differ between two commits. A missing side counts as an empty
+/// tree, so new and deleted labels report all of their files.
+fn changed_paths(repo: &Repository, old: Option<Oid>, new: Option<Oid>) -> Result<Vec<String>> {
+    let tree = |id: Option<Oid>| -> Result<Option<Tree<'_>>> {
+        id.map(|id| repo.find_commit(id)?.tree())
+            .transpose()
+            .map_err(Into::into)
+    };
+    let diff = repo.diff_tree_to_tree(tree(old)?.as_ref(), tree(new)?.as_ref(), None)?;
+
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect())
+}
 
-            let mut checkout_builder = git2::build::CheckoutBuilder::new();
-            checkout_builder.force();
-            repo.checkout_head(Some(&mut checkout_builder))?;
+/// Turns the `(_)` escape used for slashes in URLs back into `/`.
+pub fn unescape_label(label: &str) -> String {
+    label.replace("(_)", "/")
+}
+
+/// Clone directory used when none is configured: a temp directory keyed by
+/// the remote, so restarts reuse the previous clone.
+fn default_basedir(git_uri: &str) -> PathBuf {
+    let digest = format!("{:x}", Sha256::digest(git_uri.as_bytes()));
+    std::env::temp_dir().join(format!("config-repo-{}", &digest[..12]))
+}
+
+/// Points every local branch at its remote-tracking counterpart, discarding
+/// local divergence.
+fn reset_local_branches(repo: &Repository) -> Result<()> {
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (mut branch, _) = branch?;
+        let Some(name) = branch.name()?.map(|n| n.to_string()) else {
+            continue;
+        };
+        let Ok(upstream) = repo.find_reference(&format!("refs/remotes/origin/{}", name)) else {
+            continue;
+        };
+
+        let (Some(local), Some(remote)) = (branch.get().target(), upstream.target()) else {
+            continue;
+        };
+        if local != remote {
+            tracing::info!("Resetting branch {} to origin/{}", name, name);
+            branch.get_mut().set_target(remote, "force-pull")?;
         }
+    }
 
-        Ok(())
+    Ok(())
+}
+
+/// Builds fetch options that authenticate with `credentials` and verify SSH
+/// host keys against the configured known_hosts file.
+fn fetch_options<'a>(credentials: &'a GitCredentials, git_uri: &str) -> Result<FetchOptions<'a>> {
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(remote_callbacks(credentials, git_uri)?);
+    Ok(fetch_options)
+}
+
+/// Callbacks shared by fetches and pushes.
+fn remote_callbacks<'a>(
+    credentials: &'a GitCredentials,
+    git_uri: &str,
+) -> Result<RemoteCallbacks<'a>> {
+    let known_hosts = credentials
+        .known_hosts_path
+        .as_deref()
+        .map(KnownHosts::load)
+        .transpose()?;
+    let strict = credentials.strict_host_key_checking;
+    let port = ssh_port(git_uri);
+
+    let mut callbacks = RemoteCallbacks::new();
+
+    let mut selector = CredentialSelector::new(credentials);
+    callbacks.credentials(move |_url, username_from_url, allowed| {
+        selector.next(username_from_url, allowed)
+    });
+
+    callbacks.certificate_check(move |cert, host| {
+        // TLS certificates are left to libgit2's own verification
+        let Some(hostkey) = cert.as_hostkey().and_then(|k| k.hostkey()) else {
+            return Ok(CertificateCheckStatus::CertificatePassthrough);
+        };
+        check_host_key(known_hosts.as_ref(), strict, host, port, hostkey)
+    });
+
+    Ok(callbacks)
+}
+
+/// Picks the credentials libgit2 asks for. libgit2 keeps asking while
+/// authentication fails, so each method is offered at most once.
+struct CredentialSelector<'a> {
+    credentials: &'a GitCredentials,
+    ssh_attempts: usize,
+    password_tried: bool,
+}
+
+impl<'a> CredentialSelector<'a> {
+    fn new(credentials: &'a GitCredentials) -> Self {
+        Self {
+            credentials,
+            ssh_attempts: 0,
+            password_tried: false,
+        }
+    }
+
+    fn next(
+        &mut self,
+        username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let username = username_from_url
+            .or(self.credentials.username.as_deref())
+            .unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let passphrase = self.credentials.ssh_passphrase.as_deref();
+            let mut methods: Vec<Box<dyn Fn() -> Result<Cred, git2::Error>>> = Vec::new();
+            if let Some(key) = &self.credentials.ssh_private_key {
+                methods.push(Box::new(move || {
+                    Cred::ssh_key_from_memory(username, None, key, passphrase)
+                }));
+            }
+            if let Some(path) = &self.credentials.ssh_private_key_path {
+                methods.push(Box::new(move || {
+                    Cred::ssh_key(username, None, Path::new(path), passphrase)
+                }));
+            }
+            if self.credentials.ssh_agent {
+                methods.push(Box::new(move || Cred::ssh_key_from_agent(username)));
+            }
+
+            self.ssh_attempts += 1;
+            if let Some(method) = methods.get(self.ssh_attempts - 1) {
+                return method();
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !self.password_tried
+            && let (Some(user), Some(pass)) =
+                (&self.credentials.username, &self.credentials.password)
+        {
+            self.password_tried = true;
+            return Cred::userpass_plaintext(user, pass);
+        }
+
+        Err(git2::Error::from_str(
+            "No usable Git credentials configured",
+        ))
+    }
+}
+
+/// Verifies the host key an SSH server presented for `host` on `port`.
+/// Without a known_hosts file, strict checking leaves the decision to
+/// libgit2.
+fn check_host_key(
+    known_hosts: Option<&KnownHosts>,
+    strict: bool,
+    host: &str,
+    port: u16,
+    hostkey: &[u8],
+) -> Result<CertificateCheckStatus, git2::Error> {
+    let Some(known_hosts) = known_hosts else {
+        return Ok(if strict {
+            CertificateCheckStatus::CertificatePassthrough
+        } else {
+            CertificateCheckStatus::CertificateOk
+        });
+    };
+
+    match known_hosts.check(host, port, hostkey) {
+        HostKeyStatus::Match => Ok(CertificateCheckStatus::CertificateOk),
+        HostKeyStatus::Unknown if !strict => {
+            tracing::warn!("Accepting unknown SSH host key for {}", host);
+            Ok(CertificateCheckStatus::CertificateOk)
+        }
+        HostKeyStatus::Unknown => Err(git2::Error::from_str(&format!(
+            "Host {} is not in the known_hosts file",
+            host
+        ))),
+        HostKeyStatus::Mismatch => Err(git2::Error::from_str(&format!(
+            "SSH host key for {} does not match the known_hosts file",
+            host
+        ))),
+    }
+}
+
+/// The port of an SSH remote, taken from `ssh://[user@]host[:port]/path`
+/// URIs; scp-like `user@host:path` URIs always use port 22.
+fn ssh_port(git_uri: &str) -> u16 {
+    git_uri
+        .strip_prefix("ssh://")
+        .or_else(|| git_uri.strip_prefix("git+ssh://"))
+        .and_then(|rest| {
+            let authority = rest.split('/').next()?;
+            let host = authority
+                .rsplit_once('@')
+                .map_or(authority, |(_, host)| host);
+            let port = match host.strip_prefix('[') {
+                Some(bracketed) => bracketed.split_once("]:")?.1,
+                None => host.split_once(':')?.1,
+            };
+            port.parse().ok()
+        })
+        .unwrap_or(22)
+}
+
+/// Resolves a label to a commit. Branches are tried first, preferring the
+/// remote-tracking branch so fetched updates are served without a checkout,
+/// then tags, then full or abbreviated commit ids.
+///
+/// Labels containing slashes are passed with each `/` escaped as `(_)`, e.g.
+/// `feature(_)new-db` for `feature/new-db`.
+fn resolve_label<'r>(repo: &'r Repository, label: &str) -> Result<Commit<'r>> {
+    let label = unescape_label(label);
+    for name in [
+        format!("refs/remotes/origin/{}", label),
+        format!("refs/heads/{}", label),
+        format!("refs/tags/{}", label),
+    ] {
+        if let Ok(reference) = repo.find_reference(&name) {
+            return Ok(reference.peel_to_commit()?);
+        }
+    }
+
+    let is_commit_id =
+        (4..=40).contains(&label.len()) && label.chars().all(|c| c.is_ascii_hexdigit());
+    if is_commit_id
+        && let Ok(object) = repo.revparse_single(&label)
+        && let Ok(commit) = object.peel_to_commit()
+    {
+        return Ok(commit);
+    }
+
+    Err(LabelNotFound(label).into())
+}
+
+/// Writes `blob` at the path made of `names` below `tree`, creating missing
+/// directories, and returns the id of the new tree.
+fn insert_blob(repo: &Repository, tree: Option<&Tree>, names: &[&str], blob: Oid) -> Result<Oid> {
+    let mut builder = repo.treebuilder(tree)?;
+    match names {
+        [] => anyhow::bail!("Empty document path"),
+        [name] => {
+            builder.insert(name, blob, 0o100644)?;
+        }
+        [name, rest @ ..] => {
+            let subtree = match tree.and_then(|tree| tree.get_name(name)) {
+                Some(entry) if entry.kind() == Some(ObjectType::Tree) => {
+                    Some(repo.find_tree(entry.id())?)
+                }
+                _ => None,
+            };
+            let subtree = insert_blob(repo, subtree.as_ref(), rest, blob)?;
+            builder.insert(name, subtree, 0o040000)?;
+        }
+    }
+    Ok(builder.write()?)
+}
+
+/// Serves files from the tree of a single commit.
+struct TreeStore<'r> {
+    repo: &'r Repository,
+    tree: Tree<'r>,
+    git_uri: &'r str,
+}
+
+impl<'r> TreeStore<'r> {
+    fn new(repo: &'r Repository, commit: &Commit<'r>, git_uri: &'r str) -> Result<Self> {
+        Ok(Self {
+            repo,
+            tree: commit.tree()?,
+            git_uri,
+        })
+    }
+}
+
+impl ConfigStore for TreeStore<'_> {
+    fn read_file(&self, path: &Path) -> Result<Option<String>> {
+        let entry = match self.tree.get_path(path) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Symlinks are stored as blobs holding the link target, skip them
+        if entry.filemode() == i32::from(git2::FileMode::Link) {
+            return Ok(None);
+        }
+        let Ok(blob) = entry.to_object(self.repo)?.into_blob() else {
+            return Ok(None);
+        };
+        let content = String::from_utf8(blob.content().to_vec())
+            .with_context(|| format!("{} is not valid UTF-8", path.display()))?;
+
+        Ok(Some(content))
+    }
+
+    fn source_name(&self, path: &Path) -> String {
+        format!("{}/{}", self.git_uri.trim_end_matches('/'), path.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose};
+    use tempfile::TempDir;
+
+    const KEY: &[u8] = b"ssh-ed25519 key";
+
+    fn known_hosts(hosts: &str) -> KnownHosts {
+        KnownHosts::parse(&format!(
+            "{} ssh-ed25519 {}",
+            hosts,
+            general_purpose::STANDARD.encode(KEY)
+        ))
+    }
+
+    fn is_ok(status: Result<CertificateCheckStatus, git2::Error>) -> bool {
+        matches!(status, Ok(CertificateCheckStatus::CertificateOk))
+    }
+
+    #[test]
+    fn takes_the_ssh_port_from_the_uri() {
+        assert_eq!(ssh_port("ssh://git@git.local:2222/config.git"), 2222);
+        assert_eq!(ssh_port("ssh://[::1]:2200/config.git"), 2200);
+        assert_eq!(ssh_port("ssh://git.local/config.git"), 22);
+        assert_eq!(ssh_port("git@git.local:2222/config.git"), 22);
+        assert_eq!(ssh_port("https://git.local:8443/config.git"), 22);
+    }
+
+    #[test]
+    fn checks_host_keys_on_the_remote_port() {
+        let known = known_hosts("[git.local]:2222,github.com");
+
+        assert!(is_ok(check_host_key(
+            Some(&known),
+            true,
+            "git.local",
+            2222,
+            KEY
+        )));
+        assert!(is_ok(check_host_key(
+            Some(&known),
+            true,
+            "github.com",
+            22,
+            KEY
+        )));
+        assert!(check_host_key(Some(&known), true, "git.local", 22, KEY).is_err());
+        assert!(check_host_key(Some(&known), true, "github.com", 2222, KEY).is_err());
+        assert!(check_host_key(Some(&known), true, "git.local", 2222, b"other").is_err());
+    }
+
+    #[test]
+    fn unknown_hosts_follow_strict_checking() {
+        let known = known_hosts("github.com");
+
+        assert!(check_host_key(Some(&known), true, "git.local", 22, KEY).is_err());
+        assert!(is_ok(check_host_key(
+            Some(&known),
+            false,
+            "git.local",
+            22,
+            KEY
+        )));
+        assert!(matches!(
+            check_host_key(None, true, "git.local", 22, KEY),
+            Ok(CertificateCheckStatus::CertificatePassthrough)
+        ));
+        assert!(is_ok(check_host_key(None, false, "git.local", 22, KEY)));
+    }
+
+    #[test]
+    fn offers_each_credential_once() {
+        let credentials = GitCredentials {
+            username: Some("deploy".to_string()),
+            password: Some("secret".to_string()),
+            ssh_private_key_path: Some("/keys/id_ed25519".to_string()),
+            ssh_agent: true,
+            ..Default::default()
+        };
+        let mut selector = CredentialSelector::new(&credentials);
+        let ssh_key = CredentialType::SSH_KEY.bits();
+        let userpass = CredentialType::USER_PASS_PLAINTEXT;
+
+        let username = selector.next(None, CredentialType::USERNAME).unwrap();
+        assert_eq!(username.credtype(), CredentialType::USERNAME.bits());
+
+        // Key file, then the agent, then nothing
+        let key = selector.next(Some("git"), CredentialType::SSH_KEY).unwrap();
+        assert_eq!(key.credtype(), ssh_key);
+        let agent = selector.next(Some("git"), CredentialType::SSH_KEY).unwrap();
+        assert_eq!(agent.credtype(), ssh_key);
+        assert!(selector.next(Some("git"), CredentialType::SSH_KEY).is_err());
+
+        let password = selector.next(None, userpass).unwrap();
+        assert_eq!(password.credtype(), userpass.bits());
+        assert!(selector.next(None, userpass).is_err());
+    }
+
+    #[test]
+    fn local_remotes_are_fetched_with_strict_host_key_checking() {
+        let dir = TempDir::new().unwrap();
+        let remote = Repository::init_bare(dir.path().join("remote.git")).unwrap();
+        let tree = remote
+            .find_tree(remote.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        let signature = Signature::now("Config Admin", "admin@example.com").unwrap();
+        remote
+            .commit(
+                Some("refs/heads/master"),
+                &signature,
+                &signature,
+                "Initial",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        let uri = format!("file://{}", dir.path().join("remote.git").display());
+
+        let known_hosts_path = dir.path().join("known_hosts");
+        std::fs::write(&known_hosts_path, "github.com ssh-ed25519 AAAA\n").unwrap();
+        let mut credentials = GitCredentials {
+            known_hosts_path: Some(known_hosts_path.display().to_string()),
+            strict_host_key_checking: true,
+            ..Default::default()
+        };
+        let options = GitOptions {
+            basedir: Some(dir.path().join("clone").display().to_string()),
+            ..Default::default()
+        };
+
+        let repository = GitRepository::new(&uri, &credentials, &options, &[]).unwrap();
+        assert!(repository.fetch().is_ok());
+
+        // The callbacks load the known_hosts file before connecting
+        credentials.known_hosts_path = Some(dir.path().join("missing").display().to_string());
+        let repository = GitRepository {
+            credentials,
+            ..repository
+        };
+        assert!(repository.fetch().is_err());
     }
 }
@@ -0,0 +1,277 @@
+use crate::utils::pattern;
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// Outcome of looking up a host key in a known_hosts file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    Match,
+    /// The host is listed with a different key, or the key is revoked
+    Mismatch,
+    Unknown,
+}
+
+/// The entries of an OpenSSH known_hosts file. Plain and hashed (`|1|`) host
+/// names, wildcards, negations and `@revoked` markers are understood;
+/// `@cert-authority` lines are ignored.
+pub struct KnownHosts {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    hosts: Hosts,
+    key: Vec<u8>,
+    revoked: bool,
+}
+
+enum Hosts {
+    Patterns(Vec<String>),
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+}
+
+impl KnownHosts {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read known_hosts file {}", path))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let entries = content
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(parse_entry)
+            .collect();
+        Self { entries }
+    }
+
+    /// Checks the raw public key blob presented by `host` on `port`. Hosts on
+    /// other ports than 22 are listed as `[host]:port`, like OpenSSH does.
+    pub fn check(&self, host: &str, port: u16, key: &[u8]) -> HostKeyStatus {
+        let host = match port {
+            22 => host.to_string(),
+            port => format!("[{}]:{}", host, port),
+        };
+        let entries: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|e| e.hosts.matches(&host))
+            .collect();
+
+        if entries.iter().any(|e| e.revoked && e.key == key) {
+            return HostKeyStatus::Mismatch;
+        }
+
+        let mut known = false;
+        for entry in entries.iter().filter(|e| !e.revoked) {
+            if entry.key == key {
+                return HostKeyStatus::Match;
+            }
+            known = true;
+        }
+
+        if known {
+            HostKeyStatus::Mismatch
+        } else {
+            HostKeyStatus::Unknown
+        }
+    }
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.split_whitespace();
+    let mut hosts = fields.next()?;
+
+    let mut revoked = false;
+    if let Some(marker) = hosts.strip_prefix('@') {
+        match marker {
+            "revoked" => revoked = true,
+            _ => return None,
+        }
+        hosts = fields.next()?;
+    }
+
+    let _key_type = fields.next()?;
+    let key = general_purpose::STANDARD.decode(fields.next()?).ok()?;
+
+    let hosts = match hosts.strip_prefix("|1|") {
+        Some(hashed) => {
+            let (salt, hash) = hashed.split_once('|')?;
+            Hosts::Hashed {
+                salt: general_purpose::STANDARD.decode(salt).ok()?,
+                hash: general_purpose::STANDARD.decode(hash).ok()?,
+            }
+        }
+        None => Hosts::Patterns(hosts.split(',').map(|h| h.to_string()).collect()),
+    };
+
+    Some(Entry {
+        hosts,
+        key,
+        revoked,
+    })
+}
+
+impl Hosts {
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Hosts::Patterns(patterns) => {
+                let mut matched = false;
+                for p in patterns {
+                    match p.strip_prefix('!') {
+                        Some(negated) if pattern::matches(negated, host) => return false,
+                        Some(_) => {}
+                        None => matched |= pattern::matches(p, host),
+                    }
+                }
+                matched
+            }
+            Hosts::Hashed { salt, hash } => {
+                let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(salt) else {
+                    return false;
+                };
+                mac.update(host.as_bytes());
+                mac.verify_slice(hash).is_ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &[u8] = b"ssh-ed25519 key a";
+    const KEY_B: &[u8] = b"ssh-ed25519 key b";
+
+    fn encode(key: &[u8]) -> String {
+        general_purpose::STANDARD.encode(key)
+    }
+
+    fn hashed_host(host: &str, salt: &[u8]) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(salt).unwrap();
+        mac.update(host.as_bytes());
+        format!(
+            "|1|{}|{}",
+            general_purpose::STANDARD.encode(salt),
+            general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    #[test]
+    fn matches_plain_host_names() {
+        let known = KnownHosts::parse(&format!(
+            "# comment\n\ngithub.com,gitlab.com ssh-ed25519 {}\n",
+            encode(KEY_A)
+        ));
+
+        assert_eq!(known.check("github.com", 22, KEY_A), HostKeyStatus::Match);
+        assert_eq!(known.check("gitlab.com", 22, KEY_A), HostKeyStatus::Match);
+        assert_eq!(
+            known.check("github.com", 22, KEY_B),
+            HostKeyStatus::Mismatch
+        );
+        assert_eq!(
+            known.check("example.com", 22, KEY_A),
+            HostKeyStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn matches_hashed_host_names() {
+        let known = KnownHosts::parse(&format!(
+            "{} ssh-ed25519 {}",
+            hashed_host("git.internal", b"0123456789abcdef0123"),
+            encode(KEY_A)
+        ));
+
+        assert_eq!(known.check("git.internal", 22, KEY_A), HostKeyStatus::Match);
+        assert_eq!(
+            known.check("git.internal", 22, KEY_B),
+            HostKeyStatus::Mismatch
+        );
+        assert_eq!(
+            known.check("git.external", 22, KEY_A),
+            HostKeyStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn matches_wildcards_and_negations() {
+        let known = KnownHosts::parse(&format!(
+            "*.example.com,!secret.example.com ssh-ed25519 {}",
+            encode(KEY_A)
+        ));
+
+        assert_eq!(
+            known.check("git.example.com", 22, KEY_A),
+            HostKeyStatus::Match
+        );
+        assert_eq!(
+            known.check("secret.example.com", 22, KEY_A),
+            HostKeyStatus::Unknown
+        );
+        assert_eq!(
+            known.check("example.org", 22, KEY_A),
+            HostKeyStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn matches_ports_exactly() {
+        let known = KnownHosts::parse(&format!(
+            "[git.local]:2222 ssh-rsa {}\n{} ssh-rsa {}\n[*.example.com]:2222 ssh-rsa {}",
+            encode(KEY_A),
+            hashed_host("[git.hashed]:2222", b"0123456789abcdef0123"),
+            encode(KEY_A),
+            encode(KEY_A)
+        ));
+
+        assert_eq!(known.check("git.local", 2222, KEY_A), HostKeyStatus::Match);
+        assert_eq!(known.check("git.local", 22, KEY_A), HostKeyStatus::Unknown);
+        assert_eq!(
+            known.check("git.local", 2200, KEY_A),
+            HostKeyStatus::Unknown
+        );
+        assert_eq!(known.check("git.hashed", 2222, KEY_A), HostKeyStatus::Match);
+        assert_eq!(known.check("git.hashed", 22, KEY_A), HostKeyStatus::Unknown);
+        assert_eq!(
+            known.check("git.example.com", 2222, KEY_B),
+            HostKeyStatus::Mismatch
+        );
+    }
+
+    #[test]
+    fn rejects_revoked_keys() {
+        let known = KnownHosts::parse(&format!(
+            "@revoked * ssh-ed25519 {}\ngithub.com ssh-ed25519 {}\ngithub.com ssh-ed25519 {}",
+            encode(KEY_A),
+            encode(KEY_A),
+            encode(KEY_B)
+        ));
+
+        assert_eq!(
+            known.check("github.com", 22, KEY_A),
+            HostKeyStatus::Mismatch
+        );
+        assert_eq!(known.check("github.com", 22, KEY_B), HostKeyStatus::Match);
+    }
+
+    #[test]
+    fn ignores_cert_authorities_and_malformed_lines() {
+        let known = KnownHosts::parse(&format!(
+            "@cert-authority *.example.com ssh-ed25519 {}\ngithub.com ssh-ed25519 !!!\ngitlab.com",
+            encode(KEY_A)
+        ));
+
+        assert_eq!(
+            known.check("git.example.com", 22, KEY_A),
+            HostKeyStatus::Unknown
+        );
+        assert_eq!(known.check("github.com", 22, KEY_A), HostKeyStatus::Unknown);
+        assert_eq!(known.check("gitlab.com", 22, KEY_A), HostKeyStatus::Unknown);
+    }
+}
@@ -1,36 +1,114 @@
+pub mod composite;
+pub mod database;
 pub mod file;
 pub mod git;
+pub mod known_hosts;
+pub mod routing;
+pub mod vault;
+pub mod watcher;
+pub mod write;
 
-use crate::{config::ServerConfig, models::*};
+use crate::{
+    config::ServerConfig,
+    models::*,
+    utils::{document, encryption, name::InvalidName, placeholder},
+};
 use anyhow::Result;
 use dashmap::DashMap;
-use std::sync::Arc;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, broadcast},
+    time::MissedTickBehavior,
+};
+
+/// Returned when a backend needed to serve a request fails, such as an
+/// unreachable database or Vault. Carries the name of the backend.
+#[derive(Debug)]
+pub struct BackendUnavailable(pub String);
+
+impl fmt::Display for BackendUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Backend {} is unavailable", self.0)
+    }
+}
+
+impl std::error::Error for BackendUnavailable {}
 
 pub struct ConfigRepository {
     config: ServerConfig,
     cache: Arc<DashMap<String, ConfigResponse>>,
+    keyring: encryption::Keyring,
     file_repo: file::FileRepository,
     git_repo: Option<git::GitRepository>,
+    git_repos: Vec<routing::RoutedRepository>,
+    vault_repo: Option<vault::VaultRepository>,
+    database_repo: Option<database::DatabaseRepository>,
+    composite: Option<composite::CompositeRepository>,
+    changes: broadcast::Sender<()>,
+    /// Serializes writes so the version check and the write are atomic
+    write_lock: Mutex<()>,
+}
+
+/// Backend that receives writes for an application and profile.
+enum WriteTarget<'a> {
+    Git(&'a git::GitRepository),
+    Native(&'a file::FileRepository),
 }
 
 impl ConfigRepository {
     pub async fn new(config: ServerConfig) -> Result<Self> {
-        let file_repo = file::FileRepository::new(&config.config_path)?;
+        let file_repo = file::FileRepository::new(&config.config_path, &config.search_locations)?;
         let git_repo = if let Some(git_uri) = &config.git_uri {
             Some(git::GitRepository::new(
                 git_uri,
-                config.git_username.as_deref(),
-                config.git_password.as_deref(),
+                &config.git_credentials,
+                &config.git_options,
+                &config.search_locations,
             )?)
         } else {
             None
         };
 
+        let git_repos = match &config.git_repos_config {
+            Some(path) => routing::load_repos(path)?
+                .into_iter()
+                .map(routing::RoutedRepository::new)
+                .collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        let vault_repo = config
+            .vault
+            .as_ref()
+            .map(vault::VaultRepository::new)
+            .transpose()?;
+
+        let database_repo = config
+            .database
+            .as_ref()
+            .map(database::DatabaseRepository::new)
+            .transpose()?;
+
+        let composite = config
+            .composite_config
+            .as_deref()
+            .map(|path| composite::CompositeRepository::new(composite::load_backends(path)?))
+            .transpose()?;
+
+        let keyring = encryption::Keyring::from_config(&config)?;
+
         Ok(Self {
             config,
             cache: Arc::new(DashMap::new()),
+            keyring,
             file_repo,
             git_repo,
+            git_repos,
+            vault_repo,
+            database_repo,
+            composite,
+            changes: broadcast::channel(16).0,
+            write_lock: Mutex::new(()),
         })
     }
 
@@ -39,6 +117,56 @@ impl ConfigRepository {
         application: &str,
         profile: &str,
         label: &str,
+    ) -> Result<ConfigResponse> {
+        let mut config = self.load_config(application, profile, label).await?;
+        if self.config.encrypt_enabled {
+            encryption::decrypt_property_sources(&self.keyring, &mut config.property_sources);
+        }
+        Ok(config)
+    }
+
+    async fn load_config(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+    ) -> Result<ConfigResponse> {
+        let mut config = self.load_cached_config(application, profile, label).await?;
+
+        // Rows and secrets change outside of Git and the config directory, so
+        // they are read on every request rather than cached. Database rows
+        // override files, and secrets override both
+        if let Some(database_repo) = &self.database_repo {
+            config.property_sources.extend(
+                database_repo
+                    .get_property_sources(application, profile, label)
+                    .await
+                    .map_err(|e| e.context(BackendUnavailable("database".to_string())))?,
+            );
+        }
+        if let Some(vault_repo) = &self.vault_repo {
+            match vault_repo.get_property_sources(application, profile).await {
+                Ok(sources) => config.property_sources.extend(sources),
+                Err(e) if e.is::<InvalidName>() => return Err(e),
+                Err(e) if vault_repo.fail_fast() => {
+                    return Err(e.context(BackendUnavailable("vault".to_string())));
+                }
+                Err(e) => {
+                    tracing::warn!("Serving {} without Vault secrets: {:#}", application, e)
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Configuration from files and Git, cached until a refresh or a change of
+    /// the files evicts it.
+    async fn load_cached_config(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
     ) -> Result<ConfigResponse> {
         let cache_key = format!("{}:{}:{}", application, profile, label);
 
@@ -46,23 +174,350 @@ impl ConfigRepository {
             return Ok(cached.clone());
         }
 
-        // Try Git repository first, then file repository
-        let config = if let Some(git_repo) = &self.git_repo {
+        // Composite mode merges every backend; otherwise try the Git
+        // repository first, then the file repository
+        let config = if let Some(composite) = &self.composite {
+            composite.get_config(application, profile, label).await
+        } else if let Some(git_repo) = self.git_repo_for(application, profile) {
             git_repo.get_config(application, profile, label).await
-                .or_else(|_| self.file_repo.get_config(application, profile, label))
+                .or_else(|e| {
+                    file_fallback(e, || self.file_repo.get_config(application, profile, label))
+                })
         } else {
             self.file_repo.get_config(application, profile, label)
         }?;
 
-        self.cache.insert(cache_key, config.clone());
+        if self.composite.as_ref().is_none_or(|c| c.is_cacheable()) {
+            self.cache.insert(cache_key, config.clone());
+        }
         Ok(config)
     }
 
+    /// Returns the configuration with `${...}` placeholders expanded when
+    /// requested, falling back to the `RESOLVE_PLACEHOLDERS` setting.
+    pub async fn get_resolved_config(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        resolve_placeholders: Option<bool>,
+    ) -> Result<ConfigResponse> {
+        let mut config = self.get_config(application, profile, label).await?;
+        if resolve_placeholders.unwrap_or(self.config.resolve_placeholders) {
+            placeholder::resolve_property_sources(&mut config.property_sources)?;
+        }
+        Ok(config)
+    }
+
+    /// Serves a plain-text resource with `${...}` placeholders substituted
+    /// from the resolved configuration of the application and profile.
+    pub async fn get_resource(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        path: &str,
+    ) -> Result<String> {
+        let config = self.get_config(application, profile, label).await?;
+        let properties = document::merge_property_sources(&config.property_sources);
+
+        let content = if let Some(composite) = &self.composite {
+            composite
+                .get_resource(application, profile, label, path)
+                .await
+        } else if let Some(git_repo) = self.git_repo_for(application, profile) {
+            git_repo
+                .get_resource(application, profile, label, path)
+                .await
+                .or_else(|e| {
+                    file_fallback(e, || {
+                        self.file_repo
+                            .get_resource(application, profile, label, path)
+                    })
+                })
+        } else {
+            self.file_repo
+                .get_resource(application, profile, label, path)
+        }?;
+
+        placeholder::substitute(&content, &properties)
+    }
+
+    /// Writes properties of the application and profile to the backend
+    /// serving them, then evicts the affected cache entries. `version`, when
+    /// given, must match the version currently served.
+    pub async fn write_config(
+        &self,
+        application: &str,
+        profile: &str,
+        label: &str,
+        mode: write::WriteMode,
+        request: &WriteRequest,
+        author: &CommitAuthor,
+    ) -> Result<WriteResponse> {
+        let stem = write::document_stem(application, profile)?;
+        let edit = write::Edit {
+            mode,
+            properties: request.properties.clone(),
+        };
+
+        {
+            let _guard = self.write_lock.lock().await;
+            match self.write_target(application, profile)? {
+                WriteTarget::Git(git_repo) => {
+                    git_repo
+                        .write(
+                            (application, profile, label),
+                            request.version.as_deref(),
+                            &stem,
+                            &edit,
+                            author,
+                            request.message.as_deref(),
+                        )
+                        .await?;
+                }
+                WriteTarget::Native(file_repo) => {
+                    if let Some(expected) = &request.version {
+                        let actual = file_repo.get_config(application, profile, label)?.version;
+                        if actual.as_ref() != Some(expected) {
+                            return Err(write::VersionConflict {
+                                expected: expected.clone(),
+                                actual,
+                            }
+                            .into());
+                        }
+                    }
+                    file_repo.write(application, profile, label, &stem, &edit)?;
+                }
+            }
+        }
+
+        self.evict_services(&[(application.to_string(), None)]);
+        let config = self.get_config(application, profile, label).await?;
+        Ok(WriteResponse {
+            name: config.name,
+            profiles: config.profiles,
+            label: config.label,
+            version: config.version,
+        })
+    }
+
+    /// In composite mode the highest-precedence Git or native backend,
+    /// otherwise the Git repository serving the application or the file
+    /// repository.
+    fn write_target(&self, application: &str, profile: &str) -> Result<WriteTarget<'_>> {
+        if let Some(composite) = &self.composite {
+            return composite
+                .backends()
+                .iter()
+                .find_map(|backend| match &backend.source {
+                    composite::Source::Git(git_repo) => Some(WriteTarget::Git(git_repo)),
+                    composite::Source::Native(file_repo) => Some(WriteTarget::Native(file_repo)),
+                    _ => None,
+                })
+                .ok_or_else(|| {
+                    write::InvalidWrite("no Git or native backend to write to".to_string()).into()
+                });
+        }
+
+        Ok(match self.git_repo_for(application, profile) {
+            Some(git_repo) => WriteTarget::Git(git_repo),
+            None => WriteTarget::Native(&self.file_repo),
+        })
+    }
+
+    pub fn keyring(&self) -> &encryption::Keyring {
+        &self.keyring
+    }
+
+    /// The pattern-matched repository serving an application and profile.
+    /// The first matching repository in declaration order wins.
+    pub fn route(&self, application: &str, profile: &str) -> Option<&routing::RoutedRepository> {
+        self.git_repos
+            .iter()
+            .find(|r| r.config.matches(application, profile))
+    }
+
+    fn git_repo_for(&self, application: &str, profile: &str) -> Option<&git::GitRepository> {
+        match self.route(application, profile) {
+            Some(routed) => {
+                tracing::debug!(
+                    "Serving {}/{} from repository {}",
+                    application,
+                    profile,
+                    routed.config.name
+                );
+                Some(&routed.repository)
+            }
+            None => self.git_repo.as_ref(),
+        }
+    }
+
+    /// Name of the backend serving an application and profile: `composite`,
+    /// a routed repository, `default` for `GIT_URI`, or `file`.
+    pub fn repository_name(&self, application: &str, profile: &str) -> &str {
+        if self.composite.is_some() {
+            return "composite";
+        }
+        match self.route(application, profile) {
+            Some(routed) => &routed.config.name,
+            None if self.git_repo.is_some() => "default",
+            None => "file",
+        }
+    }
+
+    pub fn repositories(&self) -> &[routing::RoutedRepository] {
+        &self.git_repos
+    }
+
+    pub fn composite(&self) -> Option<&composite::CompositeRepository> {
+        self.composite.as_ref()
+    }
+
+    pub fn default_git_uri(&self) -> Option<&str> {
+        self.git_repo.as_ref().map(|r| r.uri())
+    }
+
+    /// Label used when a request does not name one, taking the matching
+    /// repository's own default into account.
+    pub fn default_label(&self, application: &str, profile: &str) -> &str {
+        self.route(application, profile)
+            .and_then(|r| r.config.default_label.as_deref())
+            .unwrap_or(&self.config.default_label)
+    }
+
+    /// Git backends paired with the name reported by [`Self::repository_name`],
+    /// or with the backend name in composite mode.
+    fn git_backends(&self) -> impl Iterator<Item = (&str, &git::GitRepository)> {
+        self.git_repo
+            .iter()
+            .map(|r| ("default", r))
+            .chain(
+                self.git_repos
+                    .iter()
+                    .map(|r| (r.config.name.as_str(), &r.repository)),
+            )
+            .chain(self.composite.iter().flat_map(|c| c.git_repositories()))
+    }
+
     pub async fn refresh(&self) -> Result<()> {
         self.cache.clear();
-        if let Some(git_repo) = &self.git_repo {
+        for (_, git_repo) in self.git_backends() {
             git_repo.pull().await?;
         }
+        self.notify_changed();
         Ok(())
     }
+
+    /// Notifies subscribers whenever cached configuration was invalidated, so
+    /// they can reload and compare. Receivers that lag behind should simply
+    /// reload as well.
+    pub fn subscribe(&self) -> broadcast::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    fn notify_changed(&self) {
+        // Fails only when nobody is subscribed
+        let _ = self.changes.send(());
+    }
+
+    /// Fetches every Git repository and evicts the cached configuration of
+    /// labels whose commit changed. Returns the changes with the name of the
+    /// repository they happened in.
+    pub async fn poll_changes(&self) -> Vec<(String, git::LabelChange)> {
+        let mut all_changes = Vec::new();
+
+        for (name, git_repo) in self.git_backends() {
+            let changes = match git_repo.pull().await {
+                Ok(changes) => changes,
+                Err(e) => {
+                    tracing::warn!("Failed to fetch repository {}: {:#}", name, e);
+                    continue;
+                }
+            };
+
+            self.evict(name, &changes);
+            all_changes.extend(changes.into_iter().map(|c| (name.to_string(), c)));
+        }
+
+        if !all_changes.is_empty() {
+            self.notify_changed();
+        }
+        all_changes
+    }
+
+    /// Polls the Git repositories every `interval`, for as long as the server
+    /// runs.
+    pub async fn poll_periodically(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // The first tick completes immediately and the clone is fresh
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            self.poll_changes().await;
+        }
+    }
+
+    /// Evicts the cached configuration of the given application and profile
+    /// pairs for every label. The `application` name matches every
+    /// application, and a missing profile matches every profile.
+    pub fn evict_services(&self, services: &[(String, Option<String>)]) {
+        self.cache.retain(|key, _| {
+            let Some((application, profile, _)) = split_cache_key(key) else {
+                return true;
+            };
+
+            let stale = services.iter().any(|(app, prof)| {
+                (app == "application" || app == application)
+                    && prof
+                        .as_deref()
+                        .is_none_or(|p| profile.split(',').any(|active| active.trim() == p))
+            });
+            if stale {
+                tracing::debug!("Evicting cached configuration {}", key);
+            }
+            !stale
+        });
+        self.notify_changed();
+    }
+
+    fn evict(&self, repository_name: &str, changes: &[git::LabelChange]) {
+        if changes.is_empty() {
+            return;
+        }
+
+        self.cache.retain(|key, _| {
+            let Some((application, profile, label)) = split_cache_key(key) else {
+                return true;
+            };
+
+            let label = git::unescape_label(label);
+            // Every composite backend contributes to every application
+            let stale = changes.iter().any(|c| c.label == label)
+                && (self.composite.is_some()
+                    || self.repository_name(application, profile) == repository_name);
+            if stale {
+                tracing::debug!("Evicting cached configuration {}", key);
+            }
+            !stale
+        });
+    }
+}
+
+/// Splits an `application:profile:label` cache key.
+fn split_cache_key(key: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = key.splitn(3, ':');
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+/// Falls back to the file repository when a Git read fails, except for labels
+/// that do not exist, which must surface as 404 rather than silently serving
+/// local files.
+fn file_fallback<T>(err: anyhow::Error, fallback: impl FnOnce() -> Result<T>) -> Result<T> {
+    if err.is::<git::LabelNotFound>() {
+        return Err(err);
+    }
+    fallback()
 }
@@ -0,0 +1,84 @@
+use super::git::GitRepository;
+use crate::{
+    config::{GitCredentials, GitOptions},
+    utils::pattern,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// A Git repository that serves the applications matched by its patterns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoConfig {
+    pub name: String,
+    pub uri: String,
+    /// `{application}` or `{application}/{profile}` wildcard patterns, e.g.
+    /// `billing-*` or `*/prod`
+    pub patterns: Vec<String>,
+    pub default_label: Option<String>,
+    #[serde(default)]
+    pub search_paths: Vec<String>,
+    #[serde(flatten)]
+    pub credentials: GitCredentials,
+    #[serde(flatten)]
+    pub options: GitOptions,
+}
+
+impl RepoConfig {
+    /// A pattern without a profile part matches every profile; otherwise any
+    /// of the requested profiles must match.
+    pub fn matches(&self, application: &str, profile: &str) -> bool {
+        self.patterns.iter().any(|p| {
+            let (app_pattern, profile_pattern) = p.split_once('/').unwrap_or((p, "*"));
+            pattern::matches(app_pattern, application)
+                && profile
+                    .split(',')
+                    .any(|prof| pattern::matches(profile_pattern, prof.trim()))
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ReposFile {
+    #[serde(default)]
+    repos: Vec<RepoConfig>,
+}
+
+pub fn load_repos(path: &str) -> Result<Vec<RepoConfig>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read repositories file {}", path))?;
+    let file: ReposFile = serde_yaml::from_str(&content)
+        .with_context(|| format!("Invalid repositories file {}", path))?;
+    Ok(file.repos)
+}
+
+pub struct RoutedRepository {
+    pub config: RepoConfig,
+    pub repository: GitRepository,
+}
+
+impl RoutedRepository {
+    pub fn new(config: RepoConfig) -> Result<Self> {
+        let repository = GitRepository::new(
+            &config.uri,
+            &config.credentials,
+            &config.options,
+            &config.search_paths,
+        )
+        .with_context(|| format!("Failed to open repository {}", config.name))?;
+
+        Ok(Self { config, repository })
+    }
+}
+
+/// Removes credentials embedded in a remote URL before it is displayed.
+pub fn redact_uri(uri: &str) -> String {
+    match uri.split_once("://") {
+        Some((scheme, rest)) => match rest.split_once('@') {
+            Some((userinfo, host)) if !userinfo.contains('/') => {
+                format!("{}://***@{}", scheme, host)
+            }
+            _ => uri.to_string(),
+        },
+        None => uri.to_string(),
+    }
+}
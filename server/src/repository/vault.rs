@@ -0,0 +1,241 @@
+use crate::{
+    config::{VaultAuthentication, VaultConfig},
+    models::PropertySource,
+    repository::file::flatten_json,
+    utils::name,
+};
+use anyhow::{Context, Result};
+use reqwest::{Client, Response, StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Reads secrets from a Vault KV version 2 engine. For an application and
+/// its profiles the secrets `{default_key}`, `{default_key},{profile}`,
+/// `{application}` and `{application},{profile}` are read, in order of
+/// increasing precedence, mirroring the file layout.
+pub struct VaultRepository {
+    client: Client,
+    config: VaultConfig,
+    token: Mutex<Option<VaultToken>>,
+}
+
+struct VaultToken {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl VaultToken {
+    fn is_valid(&self) -> bool {
+        self.expires_at.is_none_or(|at| Instant::now() < at)
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Deserialize)]
+struct LoginAuth {
+    client_token: String,
+    lease_duration: u64,
+}
+
+#[derive(Deserialize)]
+struct SecretResponse {
+    data: SecretData,
+}
+
+#[derive(Deserialize)]
+struct SecretData {
+    data: Option<Value>,
+}
+
+impl VaultRepository {
+    pub fn new(config: &VaultConfig) -> Result<Self> {
+        let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            token: Mutex::new(None),
+        })
+    }
+
+    pub fn fail_fast(&self) -> bool {
+        self.config.fail_fast
+    }
+
+    /// Property sources for the application and profile, lowest precedence
+    /// first. Secrets that do not exist are skipped.
+    pub async fn get_property_sources(
+        &self,
+        application: &str,
+        profile: &str,
+    ) -> Result<Vec<PropertySource>> {
+        name::validate(application, profile, "")?;
+
+        let profiles: Vec<&str> = profile
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        let mut names = vec![self.config.default_key.as_str()];
+        if application != self.config.default_key {
+            names.push(application);
+        }
+
+        let mut property_sources = Vec::new();
+        for name in names {
+            let keys = std::iter::once(name.to_string()).chain(
+                profiles
+                    .iter()
+                    .map(|p| format!("{}{}{}", name, self.config.profile_separator, p)),
+            );
+
+            for key in keys {
+                if let Some(data) = self.read_secret(&key).await? {
+                    property_sources.push(PropertySource {
+                        name: format!("vault:{}/{}", self.config.backend, key),
+                        source: flatten_json(data, String::new())?,
+                    });
+                }
+            }
+        }
+
+        Ok(property_sources)
+    }
+
+    /// Reads the latest version of a secret, or `None` if it does not exist
+    /// or was deleted.
+    async fn read_secret(&self, key: &str) -> Result<Option<Value>> {
+        let url = self.url(segments(&self.config.backend).chain(["data", key]))?;
+
+        let mut response = self.get(url.clone()).await?;
+        if response.status() == StatusCode::FORBIDDEN && self.can_login() {
+            // The token may have been revoked before its lease ran out
+            self.token.lock().await.take();
+            response = self.get(url).await?;
+        }
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let secret: SecretResponse = response
+                    .json()
+                    .await
+                    .with_context(|| format!("Invalid Vault response for {}", key))?;
+                Ok(secret.data.data)
+            }
+            status => anyhow::bail!("Vault returned {} reading {}", status, key),
+        }
+    }
+
+    /// Builds `{address}/v1/{segments}`. Every segment is percent-encoded, so
+    /// a key cannot address another path.
+    fn url<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> Result<Url> {
+        let mut url = Url::parse(&self.config.address)
+            .with_context(|| format!("Invalid Vault address {}", self.config.address))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid Vault address {}", self.config.address))?
+            .pop_if_empty()
+            .push("v1")
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn get(&self, url: Url) -> Result<Response> {
+        let token = self.token().await?;
+        let mut request = self.client.get(url).header("X-Vault-Token", token);
+        if let Some(namespace) = &self.config.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        Ok(request.send().await?)
+    }
+
+    fn can_login(&self) -> bool {
+        !matches!(
+            self.config.authentication,
+            VaultAuthentication::Token { .. }
+        )
+    }
+
+    /// Returns the current token, logging in again once its lease expired.
+    async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref().filter(|t| t.is_valid()) {
+            return Ok(current.value.clone());
+        }
+
+        let fresh = self.login().await?;
+        let value = fresh.value.clone();
+        *token = Some(fresh);
+        Ok(value)
+    }
+
+    async fn login(&self) -> Result<VaultToken> {
+        let (path, body) = match &self.config.authentication {
+            VaultAuthentication::Token { token } => {
+                return Ok(VaultToken {
+                    value: token.clone(),
+                    expires_at: None,
+                });
+            }
+            VaultAuthentication::AppRole {
+                role_id,
+                secret_id,
+                path,
+            } => (path, json!({ "role_id": role_id, "secret_id": secret_id })),
+            VaultAuthentication::Kubernetes {
+                role,
+                token_path,
+                path,
+            } => {
+                // Service account tokens are rotated, so read it on every login
+                let jwt = tokio::fs::read_to_string(token_path)
+                    .await
+                    .with_context(|| format!("Failed to read {}", token_path))?;
+                (path, json!({ "role": role, "jwt": jwt.trim() }))
+            }
+        };
+
+        let url = self.url(
+            std::iter::once("auth")
+                .chain(segments(path))
+                .chain(["login"]),
+        )?;
+        let mut request = self.client.post(url).json(&body);
+        if let Some(namespace) = &self.config.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Vault login at {} failed with {}", path, response.status());
+        }
+
+        let login: LoginResponse = response.json().await?;
+        tracing::debug!(
+            "Logged in to Vault at {}, lease {}s",
+            path,
+            login.auth.lease_duration
+        );
+
+        // Renew a little before the lease runs out; zero means no expiry
+        let expires_at = (login.auth.lease_duration > 0).then(|| {
+            Instant::now()
+                + Duration::from_secs(login.auth.lease_duration.saturating_sub(10).max(1))
+        });
+        Ok(VaultToken {
+            value: login.auth.client_token,
+            expires_at,
+        })
+    }
+}
+
+/// Splits a mount or auth path like `kv/team` into its segments.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
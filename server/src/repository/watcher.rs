@@ -0,0 +1,61 @@
+use super::ConfigRepository;
+use crate::utils::document;
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Watches the native configuration directory and evicts the cached
+/// configuration of the applications whose files change. Events arriving
+/// within `debounce` of each other are handled together. The returned watcher
+/// must be kept alive.
+pub fn watch(
+    repository: Arc<ConfigRepository>,
+    config_path: &str,
+    debounce: Duration,
+) -> Result<RecommendedWatcher> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Vec<PathBuf>>();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        let _ = sender.send(event.paths);
+    })?;
+    watcher.watch(Path::new(config_path), RecursiveMode::Recursive)?;
+
+    tokio::spawn(async move {
+        while let Some(mut paths) = receiver.recv().await {
+            // Editors often write a file several times in quick succession
+            tokio::time::sleep(debounce).await;
+            while let Ok(more) = receiver.try_recv() {
+                paths.extend(more);
+            }
+
+            let mut files: Vec<String> = paths
+                .iter()
+                .filter_map(|p| p.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            files.dedup();
+
+            let services = document::affected_services(&files);
+            if services.is_empty() {
+                continue;
+            }
+
+            tracing::info!("Configuration files changed: {}", files.join(", "));
+            repository.evict_services(&services);
+        }
+    });
+
+    Ok(watcher)
+}
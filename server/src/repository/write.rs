@@ -0,0 +1,211 @@
+use super::file::{self, flatten_json};
+use crate::utils::{
+    document::{self, DocumentFormat},
+    properties,
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
+
+/// Returned when a write is based on a version other than the current one.
+#[derive(Debug)]
+pub struct VersionConflict {
+    pub expected: String,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Configuration is at version {}, not {}",
+            self.actual.as_deref().unwrap_or("unknown"),
+            self.expected
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+/// Returned for writes that cannot be applied as requested.
+#[derive(Debug)]
+pub struct InvalidWrite(pub String);
+
+impl fmt::Display for InvalidWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid write: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidWrite {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Replace every property of the document
+    Replace,
+    /// Set the given keys and delete those set to `null`
+    Merge,
+}
+
+/// A change to the properties of a single document.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub mode: WriteMode,
+    pub properties: BTreeMap<String, Value>,
+}
+
+impl Edit {
+    /// Applies the edit to a document, keeping its format. Properties files
+    /// keep their comments and key order. YAML and JSON documents are
+    /// re-serialized from their properties, which sorts keys; YAML documents
+    /// with comments are refused rather than losing them.
+    pub fn apply(&self, content: Option<&str>, format: DocumentFormat) -> Result<String> {
+        let updates = self.updates()?;
+
+        if format == DocumentFormat::Properties {
+            return self.apply_properties(content.unwrap_or_default(), updates);
+        }
+        if format == DocumentFormat::Yaml && content.is_some_and(has_yaml_comments) {
+            return Err(InvalidWrite(
+                "the YAML document has comments, which rewriting it would drop".to_string(),
+            )
+            .into());
+        }
+
+        let mut properties: BTreeMap<String, Value> = match content {
+            Some(content) => file::parse_content(format, content)?.into_iter().collect(),
+            None => BTreeMap::new(),
+        };
+        properties.retain(|key, _| !self.removes(key));
+        properties.extend(updates);
+
+        let root = document::unflatten(&properties).map_err(|e| InvalidWrite(e.to_string()))?;
+        Ok(match format {
+            DocumentFormat::Json => serde_json::to_string_pretty(&root)? + "\n",
+            _ => serde_yaml::to_string(&root)?,
+        })
+    }
+
+    /// Rewrites entries in place, dropping removed keys and appending new
+    /// ones. Values must not contain control characters, which would need
+    /// escapes other readers may not understand.
+    fn apply_properties(
+        &self,
+        content: &str,
+        mut updates: BTreeMap<String, Value>,
+    ) -> Result<String> {
+        for value in updates.values() {
+            let value = document::value_to_string(value);
+            if value.contains(char::is_control) {
+                return Err(
+                    InvalidWrite(format!("value {:?} contains control characters", value)).into(),
+                );
+            }
+        }
+
+        let mut output = String::new();
+        for line in properties::lines(content) {
+            if let Some((key, _)) = &line.entry {
+                if let Some(value) = updates.remove(key) {
+                    output.push_str(&properties::format_entry(
+                        key,
+                        &document::value_to_string(&value),
+                    ));
+                    output.push('\n');
+                    continue;
+                }
+                if self.removes(key) {
+                    continue;
+                }
+            }
+            output.push_str(line.raw);
+            output.push('\n');
+        }
+
+        for (key, value) in updates {
+            output.push_str(&properties::format_entry(
+                &key,
+                &document::value_to_string(&value),
+            ));
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    /// Flattened properties written by the edit.
+    fn updates(&self) -> Result<BTreeMap<String, Value>> {
+        let mut updates = BTreeMap::new();
+        for (key, value) in &self.properties {
+            if key.trim().is_empty() {
+                return Err(InvalidWrite("property keys must not be empty".to_string()).into());
+            }
+            if !value.is_null() {
+                updates.extend(flatten_json(value.clone(), key.clone())?);
+            }
+        }
+
+        let mut keys = self.properties.keys().chain(updates.keys());
+        if let Some(key) = keys.find(|key| key.contains(char::is_control)) {
+            return Err(InvalidWrite(format!("key {:?} contains control characters", key)).into());
+        }
+        Ok(updates)
+    }
+
+    /// Whether an existing key is replaced or deleted. Setting `db` also
+    /// removes `db.url` and `db.hosts[0]`.
+    fn removes(&self, key: &str) -> bool {
+        self.mode == WriteMode::Replace
+            || self.properties.keys().any(|k| {
+                key == k
+                    || key
+                        .strip_prefix(k.as_str())
+                        .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+            })
+    }
+}
+
+/// Name, without extension, of the document holding the properties of an
+/// application and a single profile: `{application}-{profile}`, or
+/// `{application}` for the `default` profile.
+pub fn document_stem(application: &str, profile: &str) -> Result<String> {
+    let profile = profile.trim();
+    if profile.contains(',') {
+        return Err(InvalidWrite("writes target a single profile".to_string()).into());
+    }
+
+    for name in [application, profile] {
+        if name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(InvalidWrite(format!("invalid name {}", name)).into());
+        }
+    }
+
+    Ok(match profile {
+        "" | "default" => application.to_string(),
+        profile => format!("{}-{}", application, profile),
+    })
+}
+
+/// Whether a YAML document has a comment: a `#` at the start of a line or
+/// after whitespace, outside of a quoted scalar.
+fn has_yaml_comments(content: &str) -> bool {
+    content.lines().any(|line| {
+        let mut quote = None;
+        let mut previous = ' ';
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (None, '#') if previous.is_whitespace() => return true,
+                (None, '"' | '\'') if previous.is_whitespace() || "[{,".contains(previous) => {
+                    quote = Some(c)
+                }
+                (Some('"'), '\\') => {
+                    chars.next();
+                }
+                (Some(q), c) if c == q => quote = None,
+                _ => {}
+            }
+            previous = c;
+        }
+        false
+    })
+}
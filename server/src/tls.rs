@@ -0,0 +1,246 @@
+use crate::config::TlsConfig;
+use anyhow::{Context, Result};
+use axum::{Router, body::Body, extract::Request};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+/// Subject of the certificate presented by an mTLS client, stored in the
+/// request extensions so authentication can map it to a principal.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    pub subject: String,
+    pub common_name: Option<String>,
+}
+
+/// A rustls configuration that is rebuilt whenever the certificate, key or
+/// client CA files change on disk, so rotated certificates are picked up
+/// without a restart.
+pub struct ReloadableTls {
+    config: TlsConfig,
+    verify_clients: bool,
+    current: RwLock<Arc<rustls::ServerConfig>>,
+}
+
+impl ReloadableTls {
+    pub fn new(config: &TlsConfig, verify_clients: bool) -> Result<Arc<Self>> {
+        let current = build_server_config(config, verify_clients)?;
+        Ok(Arc::new(Self {
+            config: config.clone(),
+            verify_clients,
+            current: RwLock::new(Arc::new(current)),
+        }))
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let config = self.current.read().expect("TLS config lock poisoned");
+        TlsAcceptor::from(config.clone())
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let config = build_server_config(&self.config, self.verify_clients)?;
+        *self.current.write().expect("TLS config lock poisoned") = Arc::new(config);
+        Ok(())
+    }
+
+    /// Watches the directories holding the certificate files and reloads on
+    /// change. The returned watcher must be kept alive.
+    pub fn watch(self: &Arc<Self>) -> Result<RecommendedWatcher> {
+        let files: Vec<PathBuf> = [
+            Some(&self.config.cert_path),
+            Some(&self.config.key_path),
+            self.config.client_ca_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect();
+
+        let tls = Arc::clone(self);
+        let watched = files.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                // Secret mounts swap files via symlinks, so compare by file name
+                let relevant = event.paths.iter().any(|changed| {
+                    watched
+                        .iter()
+                        .any(|file| file.file_name() == changed.file_name())
+                        || changed.file_name().is_some_and(|n| n == "..data")
+                });
+                if !relevant {
+                    return;
+                }
+
+                match tls.reload() {
+                    Ok(()) => tracing::info!("Reloaded TLS certificates"),
+                    Err(e) => tracing::error!("Failed to reload TLS certificates: {:#}", e),
+                }
+            })?;
+
+        for dir in files.iter().filter_map(|f| f.parent()) {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(watcher)
+    }
+}
+
+fn build_server_config(config: &TlsConfig, verify_clients: bool) -> Result<rustls::ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = load_certs(&config.cert_path)?;
+    let key = load_key(&config.key_path)?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match (&config.client_ca_path, verify_clients) {
+        (Some(ca_path), true) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("Invalid client CA bundle")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_config)
+}
+
+pub(crate) fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate file {}", path))?;
+
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path);
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Invalid private key file {}", path))?
+        .with_context(|| format!("No private key found in {}", path))
+}
+
+fn client_certificate(cert: &CertificateDer<'_>) -> Option<ClientCertificate> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let subject = parsed.subject();
+
+    Some(ClientCertificate {
+        subject: subject.to_string(),
+        common_name: subject
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(|cn| cn.to_string()),
+    })
+}
+
+/// Time a client has to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before accepting again after `accept` failed.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serves `app` on `listener`, terminating TLS when `tls` is set.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    tls: Option<Arc<ReloadableTls>>,
+) -> Result<()> {
+    let Some(tls) = tls else {
+        axum::serve(listener, app).await?;
+        return Ok(());
+    };
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("Failed to accept connection: {}", e);
+                // Errors like EMFILE persist until connections close, so
+                // back off instead of spinning
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let acceptor = tls.acceptor();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("TLS handshake with {} timed out", peer);
+                        return;
+                    }
+                };
+
+            let certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(client_certificate);
+
+            let service =
+                hyper::service::service_fn(move |request: Request<hyper::body::Incoming>| {
+                    let mut request = request.map(Body::new);
+                    if let Some(certificate) = &certificate {
+                        request.extensions_mut().insert(certificate.clone());
+                    }
+                    app.clone().oneshot(request)
+                });
+
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Connection with {} closed: {}", peer, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{models::PropertySource, utils::properties};
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Yaml,
+    Properties,
+    Json,
+}
+
+impl DocumentFormat {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "yml" | "yaml" => Some(Self::Yaml),
+            "properties" => Some(Self::Properties),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Yaml => "text/yaml; charset=utf-8",
+            Self::Properties => "text/plain; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// Splits a Spring-style document name such as `myapp-dev.yml` into
+/// application, profile and format. The profile is taken from the last `-`
+/// so that hyphenated application names keep working.
+pub fn parse_document_name(document: &str) -> Option<(String, String, DocumentFormat)> {
+    let (name, ext) = document.rsplit_once('.')?;
+    let format = DocumentFormat::from_extension(ext)?;
+    let (application, profile) = name.rsplit_once('-')?;
+
+    if application.is_empty() || profile.is_empty() {
+        return None;
+    }
+
+    Some((application.to_string(), profile.to_string(), format))
+}
+
+/// Maps changed configuration files to the application and profile they
+/// belong to, following the `{application}-{profile}.{ext}` naming scheme.
+/// Because application names may contain `-`, a file such as
+/// `billing-api.yml` yields both `billing-api` (all profiles) and
+/// `billing` with profile `api`. Files that are not configuration documents
+/// are ignored.
+pub fn affected_services(paths: &[String]) -> Vec<(String, Option<String>)> {
+    let mut services = BTreeSet::new();
+
+    for path in paths {
+        let Some(file_name) = Path::new(path).file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some((name, ext)) = file_name.rsplit_once('.') else {
+            continue;
+        };
+        if DocumentFormat::from_extension(ext).is_none() || name.is_empty() {
+            continue;
+        }
+
+        services.insert((name.to_string(), None));
+        if let Some((application, profile)) = name.rsplit_once('-')
+            && !application.is_empty()
+            && !profile.is_empty()
+        {
+            services.insert((application.to_string(), Some(profile.to_string())));
+        }
+    }
+
+    services.into_iter().collect()
+}
+
+/// Merges property sources into a single map. Sources are ordered from lowest
+/// to highest priority, so later sources override earlier ones.
+pub fn merge_property_sources(sources: &[PropertySource]) -> BTreeMap<String, Value> {
+    let mut merged = BTreeMap::new();
+    for source in sources {
+        for (key, value) in &source.source {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    merged
+}
+
+/// Rebuilds a nested document from flattened `a.b[0].c` style keys. Array
+/// indexes are bounded by the number of properties, as larger ones would
+/// leave gaps that no property fills.
+pub fn unflatten(properties: &BTreeMap<String, Value>) -> Result<Value> {
+    let mut root = Value::Object(Map::new());
+    for (key, value) in properties {
+        if !insert_path(&mut root, &parse_key(key), value.clone(), properties.len()) {
+            anyhow::bail!("Array index out of range in {}", key);
+        }
+    }
+    Ok(root)
+}
+
+pub fn render(sources: &[PropertySource], format: DocumentFormat) -> Result<String> {
+    let merged = merge_property_sources(sources);
+
+    let output = match format {
+        DocumentFormat::Yaml => serde_yaml::to_string(&unflatten(&merged)?)?,
+        DocumentFormat::Json => serde_json::to_string_pretty(&unflatten(&merged)?)?,
+        DocumentFormat::Properties => {
+            let mut output = String::new();
+            for (key, value) in &merged {
+                output.push_str(&properties::format_entry(key, &value_to_string(value)));
+                output.push('\n');
+            }
+            output
+        }
+    };
+
+    Ok(output)
+}
+
+pub fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+fn parse_key(key: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+
+    for part in key.split('.') {
+        let (field, mut rest) = match part.find('[') {
+            Some(pos) => part.split_at(pos),
+            None => (part, ""),
+        };
+
+        if !field.is_empty() {
+            segments.push(Segment::Field(field.to_string()));
+        }
+
+        while let Some(stripped) = rest.strip_prefix('[') {
+            let Some((index, remaining)) = stripped.split_once(']') else {
+                break;
+            };
+            match index.parse() {
+                Ok(index) => segments.push(Segment::Index(index)),
+                Err(_) => segments.push(Segment::Field(index.to_string())),
+            }
+            rest = remaining;
+        }
+    }
+
+    segments
+}
+
+/// Returns `false` when an index is not below `max_index`.
+fn insert_path(node: &mut Value, path: &[Segment], value: Value, max_index: usize) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        *node = value;
+        return true;
+    };
+
+    match first {
+        Segment::Field(name) => {
+            if !node.is_object() {
+                *node = Value::Object(Map::new());
+            }
+            if let Value::Object(map) = node {
+                let child = map.entry(name.clone()).or_insert(Value::Null);
+                return insert_path(child, rest, value, max_index);
+            }
+            true
+        }
+        Segment::Index(index) => {
+            if *index >= max_index {
+                return false;
+            }
+            if !node.is_array() {
+                *node = Value::Array(Vec::new());
+            }
+            if let Value::Array(items) = node {
+                if items.len() <= *index {
+                    items.resize(index + 1, Value::Null);
+                }
+                return insert_path(&mut items[*index], rest, value, max_index);
+            }
+            true
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{config::ServerConfig, models::PropertySource};
 use aes_gcm::{
     Aes256Gcm, Nonce,
     aead::{Aead, KeyInit},
@@ -5,12 +6,326 @@ use aes_gcm::{
 use anyhow::{Context, Result};
 use base64::{Engine as _, engine::general_purpose};
 use rand::{RngCore, thread_rng};
+use rsa::{
+    Oaep, RsaPrivateKey, RsaPublicKey,
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePublicKey, LineEnding},
+};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Id of the key derived from `ENCRYPT_KEY`, used for untagged ciphertexts.
+pub const DEFAULT_KEY_ID: &str = "default";
+
+/// Reserved id of the asymmetric key. Its ciphertexts are tagged `{rsa}`.
+pub const RSA_KEY_ID: &str = "rsa";
+
+/// A 256-bit AES key derived from an arbitrary-length secret.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    bytes: [u8; 32],
+}
+
+impl EncryptionKey {
+    /// Derives the key with PBKDF2-HMAC-SHA256 so secrets of any length
+    /// (and low-entropy passphrases) map to a full-strength AES key.
+    pub fn derive(secret: &str, salt: &str) -> Self {
+        let mut bytes = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            secret.as_bytes(),
+            salt.as_bytes(),
+            PBKDF2_ITERATIONS,
+            &mut bytes,
+        );
+        Self { bytes }
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(**redacted**)")
+    }
+}
+
+/// An RSA key pair loaded from a PEM file. Anyone holding the public key can
+/// encrypt values offline; only the server can decrypt them.
+#[derive(Clone)]
+pub struct AsymmetricKey {
+    private_key: RsaPrivateKey,
+    public_key: RsaPublicKey,
+}
+
+impl AsymmetricKey {
+    /// Loads a PKCS#8 or PKCS#1 PEM-encoded RSA private key.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read RSA key {}", path.display()))?;
+
+        let private_key = RsaPrivateKey::from_pkcs8_pem(&pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+            .map_err(|e| anyhow::anyhow!("Invalid RSA private key {}: {}", path.display(), e))?;
+        let public_key = private_key.to_public_key();
+
+        Ok(Self {
+            private_key,
+            public_key,
+        })
+    }
+
+    pub fn public_key_pem(&self) -> Result<String> {
+        self.public_key
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(|e| anyhow::anyhow!("Failed to encode public key: {}", e))
+    }
+
+    fn decrypt(&self, encrypted_data: &str) -> Result<String> {
+        let data = general_purpose::STANDARD
+            .decode(encrypted_data)
+            .context("Failed to decode base64 data")?;
+
+        // Length-prefixed RSA-wrapped AES key, followed by the AES-GCM payload
+        if data.len() < 2 {
+            return Err(anyhow::anyhow!("Invalid encrypted data: too short"));
+        }
+        let wrapped_len = u16::from_be_bytes([data[0], data[1]]) as usize;
+        if data.len() < 2 + wrapped_len {
+            return Err(anyhow::anyhow!("Invalid encrypted data: too short"));
+        }
+        let (wrapped_key, payload) = data[2..].split_at(wrapped_len);
+
+        let key_bytes = self
+            .private_key
+            .decrypt(Oaep::new::<Sha256>(), wrapped_key)
+            .map_err(|e| anyhow::anyhow!("Failed to unwrap data key: {}", e))?;
+        let key = EncryptionKey {
+            bytes: key_bytes
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid data key length"))?,
+        };
+
+        decrypt_bytes(&key, payload)
+    }
+}
+
+impl fmt::Debug for AsymmetricKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AsymmetricKey(**redacted**)")
+    }
+}
+
+/// Parses a PEM-encoded RSA public key, as published by the `/key` endpoint.
+pub fn parse_public_key(pem: &str) -> Result<RsaPublicKey> {
+    RsaPublicKey::from_public_key_pem(pem)
+        .map_err(|e| anyhow::anyhow!("Invalid RSA public key: {}", e))
+}
+
+/// Encrypts a value with the server's public key, producing a
+/// `{cipher}{rsa}...` value. A random AES key encrypts the plaintext and is
+/// itself wrapped with RSA-OAEP, so values of any length can be encrypted.
+pub fn encrypt_with_public_key(public_key: &RsaPublicKey, plaintext: &str) -> Result<String> {
+    let mut key = EncryptionKey { bytes: [0u8; 32] };
+    thread_rng().fill_bytes(&mut key.bytes);
+
+    let wrapped_key = public_key
+        .encrypt(&mut thread_rng(), Oaep::new::<Sha256>(), &key.bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to wrap data key: {}", e))?;
+
+    let mut result = Vec::new();
+    result.extend_from_slice(&(wrapped_key.len() as u16).to_be_bytes());
+    result.extend_from_slice(&wrapped_key);
+    result.extend_from_slice(&encrypt_bytes(&key, plaintext)?);
+
+    Ok(format!(
+        "{{cipher}}{{rsa}}{}",
+        general_purpose::STANDARD.encode(result)
+    ))
+}
+
+/// Named encryption keys. Ciphertexts carry the id of the key that produced
+/// them (`{cipher}{key:prod-2026}...`); untagged ciphertexts belong to the
+/// `default` key derived from `ENCRYPT_KEY`, and `{cipher}{rsa}...` values to
+/// the asymmetric key. New values are always encrypted with the active key.
+#[derive(Debug, Clone)]
+pub struct Keyring {
+    keys: HashMap<String, EncryptionKey>,
+    asymmetric: Option<AsymmetricKey>,
+    active: String,
+}
+
+impl Keyring {
+    pub fn from_config(config: &ServerConfig) -> Result<Self> {
+        let mut keys = HashMap::new();
+        if let Some(secret) = &config.encrypt_key {
+            keys.insert(
+                DEFAULT_KEY_ID.to_string(),
+                EncryptionKey::derive(secret, &config.encrypt_salt),
+            );
+        }
+        for (id, secret) in &config.encrypt_keys {
+            keys.insert(
+                id.clone(),
+                EncryptionKey::derive(secret, &config.encrypt_salt),
+            );
+        }
+
+        let asymmetric = config
+            .encrypt_rsa_private_key
+            .as_ref()
+            .map(AsymmetricKey::load)
+            .transpose()?;
+
+        let active = config
+            .encrypt_active_key
+            .clone()
+            .unwrap_or_else(|| DEFAULT_KEY_ID.to_string());
+        let active_configured = if active == RSA_KEY_ID {
+            asymmetric.is_some()
+        } else {
+            keys.contains_key(&active)
+        };
+        if !active_configured {
+            anyhow::bail!("Active encryption key '{}' is not configured", active);
+        }
+
+        Ok(Self {
+            keys,
+            asymmetric,
+            active,
+        })
+    }
 
-const KEY: &[u8; 32] = b"default-secret-key-32-characters";
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    pub fn asymmetric_key(&self) -> Option<&AsymmetricKey> {
+        self.asymmetric.as_ref()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        if self.active == RSA_KEY_ID {
+            let asymmetric = self.asymmetric_key().context("No RSA key configured")?;
+            return encrypt_with_public_key(&asymmetric.public_key, plaintext);
+        }
+
+        let payload = encrypt_payload(&self.keys[&self.active], plaintext)?;
+
+        if self.active == DEFAULT_KEY_ID {
+            Ok(format!("{{cipher}}{}", payload))
+        } else {
+            Ok(format!("{{cipher}}{{key:{}}}{}", self.active, payload))
+        }
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String> {
+        // If not encrypted, return as-is
+        let Some(encrypted_data) = encrypted.strip_prefix("{cipher}") else {
+            return Ok(encrypted.to_string());
+        };
+
+        let (key_id, payload) = split_key_id(encrypted_data)?;
+        if key_id == RSA_KEY_ID {
+            return self
+                .asymmetric_key()
+                .context("No RSA key configured")?
+                .decrypt(payload);
+        }
+
+        let key = self
+            .keys
+            .get(key_id)
+            .with_context(|| format!("Unknown encryption key '{}'", key_id))?;
+
+        decrypt_payload(key, payload)
+    }
+
+    /// Re-encrypts a single `{cipher}` value with the active key. Values
+    /// already encrypted with the active key are returned unchanged.
+    pub fn rotate(&self, encrypted: &str) -> Result<String> {
+        let encrypted_data = encrypted
+            .strip_prefix("{cipher}")
+            .context("Invalid cipher format")?;
+
+        let (key_id, _) = split_key_id(encrypted_data)?;
+        if key_id == self.active {
+            return Ok(encrypted.to_string());
+        }
+
+        self.encrypt(&self.decrypt(encrypted)?)
+    }
+
+    /// Re-encrypts every `{cipher}` value found in a YAML, properties or JSON
+    /// document, leaving the rest of the text (comments, ordering, quoting)
+    /// untouched.
+    pub fn rotate_document(&self, document: &str) -> Result<String> {
+        let mut output = String::with_capacity(document.len());
+        let mut rest = document;
+
+        while let Some(start) = rest.find("{cipher}") {
+            output.push_str(&rest[..start]);
+            let token_len = cipher_token_len(&rest[start..]);
+            let token = &rest[start..start + token_len];
+
+            output.push_str(
+                &self
+                    .rotate(token)
+                    .with_context(|| format!("Failed to re-encrypt value {}", token))?,
+            );
+            rest = &rest[start + token_len..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+/// Splits an optional `{key:<id>}` or `{rsa}` tag from the front of a
+/// ciphertext.
+fn split_key_id(encrypted_data: &str) -> Result<(&str, &str)> {
+    if let Some(payload) = encrypted_data.strip_prefix("{rsa}") {
+        return Ok((RSA_KEY_ID, payload));
+    }
 
-pub fn encrypt(plaintext: &str) -> Result<String> {
+    match encrypted_data.strip_prefix("{key:") {
+        Some(tagged) => tagged
+            .split_once('}')
+            .context("Invalid cipher format: unterminated key id"),
+        None => Ok((DEFAULT_KEY_ID, encrypted_data)),
+    }
+}
+
+/// Length of the `{cipher}` token at the start of `text`, including any key
+/// tag and the base64 payload.
+fn cipher_token_len(text: &str) -> usize {
+    let mut len = "{cipher}".len();
+
+    if text[len..].starts_with("{rsa}") {
+        len += "{rsa}".len();
+    } else if text[len..].starts_with("{key:") {
+        match text[len..].find('}') {
+            Some(end) => len += end + 1,
+            None => return text.len(),
+        }
+    }
+
+    len + text[len..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=')))
+        .unwrap_or(text.len() - len)
+}
+
+fn encrypt_payload(key: &EncryptionKey, plaintext: &str) -> Result<String> {
+    Ok(general_purpose::STANDARD.encode(encrypt_bytes(key, plaintext)?))
+}
+
+fn encrypt_bytes(key: &EncryptionKey, plaintext: &str) -> Result<Vec<u8>> {
     // Handle the InvalidLength error explicitly
-    let cipher = Aes256Gcm::new_from_slice(KEY)
+    let cipher = Aes256Gcm::new_from_slice(&key.bytes)
         .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
 
     // Generate random nonce - make sure rand is properly imported
@@ -31,28 +346,19 @@ pub fn encrypt(plaintext: &str) -> Result<String> {
     result.extend_from_slice(&nonce_bytes);
     result.extend_from_slice(&ciphertext);
 
-    Ok(format!(
-        "{{cipher}}{}",
-        general_purpose::STANDARD.encode(result)
-    ))
+    Ok(result)
 }
 
-pub fn decrypt(encrypted: &str) -> Result<String> {
-    // If not encrypted, return as-is
-    if !encrypted.starts_with("{cipher}") {
-        return Ok(encrypted.to_string());
-    }
-
-    // Remove the cipher prefix
-    let encrypted_data = encrypted
-        .strip_prefix("{cipher}")
-        .context("Invalid cipher format")?;
-
+fn decrypt_payload(key: &EncryptionKey, encrypted_data: &str) -> Result<String> {
     // Decode base64
     let data = general_purpose::STANDARD
         .decode(encrypted_data)
         .context("Failed to decode base64 data")?;
 
+    decrypt_bytes(key, &data)
+}
+
+fn decrypt_bytes(key: &EncryptionKey, data: &[u8]) -> Result<String> {
     // Ensure minimum length
     if data.len() < 12 {
         return Err(anyhow::anyhow!("Invalid encrypted data: too short"));
@@ -62,7 +368,7 @@ pub fn decrypt(encrypted: &str) -> Result<String> {
     let (nonce_bytes, ciphertext) = data.split_at(12);
 
     // Create cipher with explicit error handling
-    let cipher = Aes256Gcm::new_from_slice(KEY)
+    let cipher = Aes256Gcm::new_from_slice(&key.bytes)
         .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
 
     let nonce = Nonce::from_slice(nonce_bytes);
@@ -75,3 +381,36 @@ pub fn decrypt(encrypted: &str) -> Result<String> {
     // Convert to UTF-8 string
     String::from_utf8(plaintext).context("Decrypted data is not valid UTF-8")
 }
+
+/// Decrypts `{cipher}` values in place. Values that cannot be decrypted are
+/// replaced by an `invalid.<key>` entry so one bad secret does not fail the
+/// whole request.
+pub fn decrypt_property_sources(keyring: &Keyring, sources: &mut [PropertySource]) {
+    for source in sources.iter_mut() {
+        let encrypted_keys: Vec<String> = source
+            .source
+            .iter()
+            .filter(|(_, v)| v.as_str().is_some_and(|s| s.starts_with("{cipher}")))
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        for key in encrypted_keys {
+            let Some(value) = source.source.remove(&key) else {
+                continue;
+            };
+
+            match keyring.decrypt(value.as_str().unwrap_or_default()) {
+                Ok(plaintext) => {
+                    source.source.insert(key, Value::String(plaintext));
+                }
+                Err(e) => {
+                    tracing::warn!("Cannot decrypt key {} in {}: {}", key, source.name, e);
+                    source.source.insert(
+                        format!("invalid.{}", key),
+                        Value::String("<n/a>".to_string()),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1 +1,7 @@
+pub mod document;
 pub mod encryption;
+pub mod name;
+pub mod pattern;
+pub mod placeholder;
+pub mod properties;
+pub mod webhook;
@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Returned for an application, profile or label that could address
+/// configuration outside of its own files, Git trees or secrets.
+#[derive(Debug)]
+pub struct InvalidName(pub String);
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid name: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidName {}
+
+/// Rejects names containing `\` or `..`, and applications and profiles
+/// containing `/`. The check runs after the `(_)` escape is expanded, so a
+/// label may name a branch such as `feature/new-db`, sent as `feature(_)new-db`
+/// or `feature%2Fnew-db`.
+pub fn validate(application: &str, profile: &str, label: &str) -> Result<(), InvalidName> {
+    for (value, slashes_allowed) in [(application, false), (profile, false), (label, true)] {
+        let unescaped = value.replace("(_)", "/");
+        if unescaped.contains('\\')
+            || unescaped.contains("..")
+            || (!slashes_allowed && unescaped.contains('/'))
+        {
+            return Err(InvalidName(value.to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_may_contain_slashes() {
+        for label in ["main", "feature(_)new-db", "feature/new-db", "v1.2.0"] {
+            assert!(validate("billing", "prod", label).is_ok(), "{}", label);
+        }
+    }
+
+    #[test]
+    fn rejects_escaping_names() {
+        for (application, profile, label) in [
+            ("billing/x", "prod", "main"),
+            ("billing(_)x", "prod", "main"),
+            ("..", "prod", "main"),
+            ("billing", "a\\b", "main"),
+            ("billing", "prod", "feature/../main"),
+            ("billing", "prod", "a(_)..(_)b"),
+            ("billing", "prod", "a\\b"),
+        ] {
+            assert!(
+                validate(application, profile, label).is_err(),
+                "{} {} {}",
+                application,
+                profile,
+                label
+            );
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use super::document::{merge_property_sources, value_to_string};
+use crate::models::PropertySource;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Returned when placeholders reference each other in a loop, e.g.
+/// `a: ${b}` and `b: ${a}`. Carries the chain of keys that forms the cycle.
+#[derive(Debug)]
+pub struct CircularReference(pub Vec<String>);
+
+impl fmt::Display for CircularReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Circular placeholder reference: {}", self.0.join(" -> "))
+    }
+}
+
+impl std::error::Error for CircularReference {}
+
+/// Substitutes `${key}` and `${key:default}` placeholders in `text` with
+/// values from `properties`, resolving placeholders inside those values too.
+/// Placeholders that cannot be resolved and have no default are left untouched.
+pub fn substitute(text: &str, properties: &BTreeMap<String, Value>) -> Result<String> {
+    Resolver::new(properties).resolve(text)
+}
+
+/// Expands placeholders in every string value of the property sources. Keys
+/// are looked up in the merged view, so the highest-priority source wins.
+pub fn resolve_property_sources(sources: &mut [PropertySource]) -> Result<()> {
+    let merged = merge_property_sources(sources);
+    let mut resolver = Resolver::new(&merged);
+
+    for source in sources.iter_mut() {
+        for value in source.source.values_mut() {
+            if let Value::String(s) = value
+                && s.contains("${")
+            {
+                *s = resolver.resolve(s)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Resolver<'a> {
+    properties: &'a BTreeMap<String, Value>,
+    stack: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(properties: &'a BTreeMap<String, Value>) -> Self {
+        Self {
+            properties,
+            stack: Vec::new(),
+        }
+    }
+
+    fn resolve(&mut self, text: &str) -> Result<String> {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find("${") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+
+            let Some(end) = find_closing_brace(after) else {
+                output.push_str(&rest[start..]);
+                return Ok(output);
+            };
+
+            let expression = &after[..end];
+            let (key, default) = match expression.split_once(':') {
+                Some((key, default)) => (key, Some(default)),
+                None => (expression, None),
+            };
+
+            match (self.properties.get(key), default) {
+                (Some(value), _) => {
+                    let value = value_to_string(value);
+                    output.push_str(&self.resolve_key(key, &value)?);
+                }
+                (None, Some(default)) => output.push_str(&self.resolve(default)?),
+                (None, None) => output.push_str(&rest[start..start + 2 + end + 1]),
+            }
+
+            rest = &after[end + 1..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn resolve_key(&mut self, key: &str, value: &str) -> Result<String> {
+        if self.stack.iter().any(|k| k == key) {
+            let mut chain = self.stack.clone();
+            chain.push(key.to_string());
+            return Err(CircularReference(chain).into());
+        }
+
+        self.stack.push(key.to_string());
+        let resolved = self.resolve(value);
+        self.stack.pop();
+        resolved
+    }
+}
+
+/// Finds the `}` that closes a placeholder, skipping over nested `${...}`
+/// expressions inside defaults.
+fn find_closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let bytes = text.as_bytes();
+
+    for (i, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'{' if i > 0 && bytes[i - 1] == b'$' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn properties(pairs: &[(&str, &str)]) -> BTreeMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), json!(value)))
+            .collect()
+    }
+
+    fn cycle(text: &str, properties: &BTreeMap<String, Value>) -> Vec<String> {
+        let err = substitute(text, properties).unwrap_err();
+        err.downcast::<CircularReference>().unwrap().0
+    }
+
+    #[test]
+    fn resolves_nested_placeholders() {
+        let properties = properties(&[("host", "db"), ("url", "jdbc://${host}:${port:5432}")]);
+
+        assert_eq!(
+            substitute("url=${url}", &properties).unwrap(),
+            "url=jdbc://db:5432"
+        );
+    }
+
+    #[test]
+    fn detects_direct_cycles() {
+        let properties = properties(&[("a", "${b}"), ("b", "${a}")]);
+
+        assert_eq!(cycle("${a}", &properties), ["a", "b", "a"]);
+        let err = substitute("${a}", &properties).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Circular placeholder reference: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn detects_cycles_through_defaults() {
+        let properties = properties(&[("a", "${missing:${a}}")]);
+
+        assert_eq!(cycle("${a}", &properties), ["a", "a"]);
+    }
+
+    #[test]
+    fn resolves_nested_defaults() {
+        let properties = properties(&[("fallback", "from-fallback")]);
+
+        assert_eq!(
+            substitute("${a:${b:${fallback}}}", &properties).unwrap(),
+            "from-fallback"
+        );
+        assert_eq!(
+            substitute("${a:${b:literal}}-${c:}", &properties).unwrap(),
+            "literal-"
+        );
+    }
+
+    #[test]
+    fn leaves_unterminated_placeholders() {
+        let properties = properties(&[("a", "1")]);
+
+        assert_eq!(
+            substitute("${a} and ${a", &properties).unwrap(),
+            "1 and ${a"
+        );
+        assert_eq!(substitute("${b:${a}", &properties).unwrap(), "${b:${a}");
+    }
+
+    #[test]
+    fn leaves_unresolvable_placeholders() {
+        let properties = properties(&[("a", "${missing}")]);
+
+        assert_eq!(
+            substitute("${a}/${other}", &properties).unwrap(),
+            "${missing}/${other}"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Reading and writing `.properties` documents as `java.util.Properties`
+//! does: keys end at an unescaped `=`, `:` or whitespace, lines ending in an
+//! odd number of backslashes continue on the next line, and `\t`, `\n`, `\r`,
+//! `\f` and `\uXXXX` are escapes.
+
+/// A logical line of a properties document.
+pub struct Line<'a> {
+    /// Text of the physical lines the logical line spans, without the final
+    /// line break
+    pub raw: &'a str,
+    /// Unescaped key and value, or `None` for blank and comment lines
+    pub entry: Option<(String, String)>,
+}
+
+/// Splits a document into logical lines, joining continuations.
+pub fn lines(content: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut physical = content
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end_matches(['\n', '\r'])))
+        })
+        .peekable();
+
+    while let Some((start, line)) = physical.next() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with(['#', '!']) {
+            lines.push(Line {
+                raw: &content[start..start + line.len()],
+                entry: None,
+            });
+            continue;
+        }
+
+        let mut logical = String::new();
+        let mut current = trimmed;
+        let mut end = start + line.len();
+        while let Some(joined) = continued(current) {
+            logical.push_str(joined);
+            let Some((next_start, next)) = physical.next() else {
+                current = "";
+                break;
+            };
+            end = next_start + next.len();
+            current = next.trim_start();
+        }
+        logical.push_str(current);
+
+        lines.push(Line {
+            raw: &content[start..end],
+            entry: Some(parse_entry(&logical)),
+        });
+    }
+    lines
+}
+
+/// Formats a `key=value` line, escaping line breaks and other control
+/// characters so the entry stays on one line.
+pub fn format_entry(key: &str, value: &str) -> String {
+    let mut line = escape(key, true);
+    line.push('=');
+    line.push_str(&escape(value, false));
+    line
+}
+
+/// The line without its continuation backslash, if it continues.
+fn continued(line: &str) -> Option<&str> {
+    let backslashes = line.len() - line.trim_end_matches('\\').len();
+    (!backslashes.is_multiple_of(2)).then(|| &line[..line.len() - 1])
+}
+
+fn parse_entry(line: &str) -> (String, String) {
+    let mut chars = line.chars().peekable();
+    let mut key = String::new();
+    while let Some(&c) = chars.peek() {
+        if matches!(c, '=' | ':') || c.is_whitespace() {
+            break;
+        }
+        chars.next();
+        push_unescaped(&mut key, c, &mut chars);
+    }
+
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if chars.next_if(|c| matches!(c, '=' | ':')).is_some() {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    // Trailing whitespace is dropped unless escaped
+    let mut value = String::new();
+    let mut kept = 0;
+    while let Some(c) = chars.next() {
+        push_unescaped(&mut value, c, &mut chars);
+        if c == '\\' || !c.is_whitespace() {
+            kept = value.len();
+        }
+    }
+    value.truncate(kept);
+
+    (key, value)
+}
+
+fn push_unescaped(
+    output: &mut String,
+    c: char,
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+) {
+    if c != '\\' {
+        output.push(c);
+        return;
+    }
+
+    match chars.next() {
+        Some('t') => output.push('\t'),
+        Some('n') => output.push('\n'),
+        Some('r') => output.push('\r'),
+        Some('f') => output.push('\u{c}'),
+        Some('u') => {
+            let hex: String = chars.clone().take(4).collect();
+            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                Some(unescaped) if hex.len() == 4 => {
+                    output.push(unescaped);
+                    chars.nth(3);
+                }
+                _ => output.push('u'),
+            }
+        }
+        Some(other) => output.push(other),
+        None => {}
+    }
+}
+
+fn escape(text: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        let escape = match c {
+            '\t' => Some('t'),
+            '\n' => Some('n'),
+            '\r' => Some('r'),
+            '\u{c}' => Some('f'),
+            '\\' => Some('\\'),
+            '=' | ':' if is_key => Some(c),
+            '#' | '!' if i == 0 => Some(c),
+            ' ' if is_key || i == 0 => Some(c),
+            _ => None,
+        };
+        match escape {
+            Some(escape) => {
+                escaped.push('\\');
+                escaped.push(escape);
+            }
+            None if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            None => escaped.push(c),
+        }
+    }
+    // Keep trailing spaces of values, which the reader would drop
+    if !is_key && text.ends_with(' ') && text.len() > 1 {
+        escaped.pop();
+        escaped.push_str("\\ ");
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(content: &str) -> Vec<(String, String)> {
+        lines(content).into_iter().filter_map(|l| l.entry).collect()
+    }
+
+    fn entry(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(
+            entries("a=1\nb: 2\nc 3\nd\t=\t4\ne\n f = \n"),
+            [
+                entry("a", "1"),
+                entry("b", "2"),
+                entry("c", "3"),
+                entry("d", "4"),
+                entry("e", ""),
+                entry("f", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let parsed = lines("# comment\n! also\n\n  a=1\\\n");
+        assert_eq!(parsed.len(), 4);
+        assert!(parsed[..3].iter().all(|l| l.entry.is_none()));
+        assert_eq!(parsed[3].entry, Some(entry("a", "1")));
+    }
+
+    #[test]
+    fn continuations() {
+        let content = "list=a,\\\n    b,\\\r\n    c\nescaped=ends\\\\\nnext=1\n";
+        let parsed = lines(content);
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].raw, "list=a,\\\n    b,\\\r\n    c");
+        assert_eq!(parsed[0].entry, Some(entry("list", "a,b,c")));
+        assert_eq!(parsed[1].entry, Some(entry("escaped", "ends\\")));
+        assert_eq!(parsed[2].entry, Some(entry("next", "1")));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            entries("a\\=b\\:c\\ d=x\\ty\\u00e9\\q\ntrailing=kept\\ \n"),
+            [entry("a=b:c d", "x\tyéq"), entry("trailing", "kept ")]
+        );
+    }
+
+    #[test]
+    fn formatted_entries_read_back() {
+        for (key, value) in [
+            ("db.url", "jdbc:h2:mem:test"),
+            ("key with=sep:s", "value = with # and !"),
+            ("#comment-like", "  padded  "),
+            ("back\\slash", "c:\\dir\\"),
+            ("unicode", "café"),
+            ("empty", ""),
+            ("multi\nline", "line1\nline2\r\n\tindented\u{c}\u{1b}"),
+        ] {
+            let line = format_entry(key, value);
+            assert_eq!(entries(&line), [entry(key, value)], "{}", line);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeSet, fmt};
+
+/// Shared secret used to verify webhook deliveries, set through
+/// `MONITOR_SECRET`.
+#[derive(Clone)]
+pub struct MonitorSecret(pub String);
+
+/// Returned when a delivery's signature or token does not match the secret.
+#[derive(Debug)]
+pub struct InvalidSignature;
+
+impl fmt::Display for InvalidSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Webhook signature is missing or invalid")
+    }
+}
+
+impl std::error::Error for InvalidSignature {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitHost {
+    GitHub,
+    Gitea,
+    GitLab,
+    Bitbucket,
+}
+
+/// A verified push notification.
+#[derive(Debug)]
+pub struct PushEvent {
+    pub host: GitHost,
+    /// Files touched by the pushed commits, or `None` when the host does not
+    /// report them (Bitbucket)
+    pub paths: Option<Vec<String>>,
+}
+
+/// Identifies the Git host from its event header, verifies the delivery
+/// against `secret` and extracts the changed paths. Returns `Ok(None)` for
+/// events other than pushes, such as GitHub's `ping`.
+///
+/// Bitbucket Cloud cannot sign deliveries, so Bitbucket may instead pass the
+/// secret as the `token` query parameter of the webhook URL.
+pub fn parse_push(
+    headers: &HeaderMap,
+    body: &[u8],
+    token: Option<&str>,
+    secret: &str,
+) -> Result<Option<PushEvent>> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    // Gitea also sends the GitHub headers, so it is checked first
+    let (host, event) = if let Some(event) = header("x-gitea-event") {
+        (GitHost::Gitea, event)
+    } else if let Some(event) = header("x-gitlab-event") {
+        (GitHost::GitLab, event)
+    } else if let Some(event) = header("x-github-event") {
+        (GitHost::GitHub, event)
+    } else if let Some(event) = header("x-event-key") {
+        (GitHost::Bitbucket, event)
+    } else {
+        anyhow::bail!("Unrecognized webhook: no event header");
+    };
+
+    let hub_signature = || {
+        header("x-hub-signature-256")
+            .or_else(|| header("x-hub-signature"))
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .is_some_and(|signature| verify_hmac(secret, body, signature))
+    };
+    let verified = match host {
+        GitHost::GitLab => {
+            header("x-gitlab-token").is_some_and(|token| verify_token(secret, token))
+        }
+        GitHost::Gitea => header("x-gitea-signature")
+            .is_some_and(|signature| verify_hmac(secret, body, signature)),
+        GitHost::GitHub => hub_signature(),
+        GitHost::Bitbucket => {
+            hub_signature() || token.is_some_and(|token| verify_token(secret, token))
+        }
+    };
+    if !verified {
+        return Err(InvalidSignature.into());
+    }
+
+    let is_push = match host {
+        GitHost::GitHub | GitHost::Gitea => event == "push",
+        GitHost::GitLab => event == "Push Hook" || event == "Tag Push Hook",
+        GitHost::Bitbucket => event == "repo:push" || event == "repo:refs_changed",
+    };
+    if !is_push {
+        return Ok(None);
+    }
+
+    let payload: Value = serde_json::from_slice(body).context("Invalid webhook payload")?;
+    let paths = match host {
+        GitHost::Bitbucket => None,
+        _ => Some(commit_paths(&payload)),
+    };
+
+    Ok(Some(PushEvent { host, paths }))
+}
+
+/// Collects the added, modified and removed files of every pushed commit.
+fn commit_paths(payload: &Value) -> Vec<String> {
+    let mut paths = BTreeSet::new();
+    for commit in payload["commits"].as_array().into_iter().flatten() {
+        for kind in ["added", "modified", "removed"] {
+            for path in commit[kind].as_array().into_iter().flatten() {
+                if let Some(path) = path.as_str() {
+                    paths.insert(path.to_string());
+                }
+            }
+        }
+    }
+    paths.into_iter().collect()
+}
+
+/// Compares digests so the comparison takes the same time for every token.
+fn verify_token(secret: &str, token: &str) -> bool {
+    Sha256::digest(token.as_bytes()) == Sha256::digest(secret.as_bytes())
+}
+
+fn verify_hmac(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(signature) = decode_hex(signature.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,107 @@
+//! Reads properties from a temporary SQLite database.
+
+use config_server::{config::DatabaseConfig, repository::database::DatabaseRepository};
+use rusqlite::Connection;
+use serde_json::json;
+use tempfile::TempDir;
+
+fn database(
+    rows: &[(&str, &str, &str, &str, rusqlite::types::Value)],
+) -> (TempDir, DatabaseConfig) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.db");
+    let connection = Connection::open(&path).unwrap();
+    connection
+        .execute(
+            "CREATE TABLE properties (application TEXT, profile TEXT, label TEXT, key TEXT, value)",
+            (),
+        )
+        .unwrap();
+    for (application, profile, label, key, value) in rows {
+        connection
+            .execute(
+                "INSERT INTO properties VALUES (?1, ?2, ?3, ?4, ?5)",
+                (application, profile, label, key, value),
+            )
+            .unwrap();
+    }
+
+    let config = DatabaseConfig {
+        url: format!("sqlite:{}", path.display()),
+        query: None,
+        ca_path: None,
+    };
+    (dir, config)
+}
+
+fn text(value: &str) -> rusqlite::types::Value {
+    value.to_string().into()
+}
+
+#[tokio::test]
+async fn reads_rows_in_order_of_precedence() {
+    let (_dir, config) = database(&[
+        ("application", "default", "master", "timeout", text("30")),
+        ("billing", "default", "master", "db.url", text("sqlite")),
+        ("billing", "prod", "master", "db.url", text("postgres")),
+        ("billing", "prod", "release", "db.url", text("other")),
+        ("orders", "default", "master", "db.url", text("orders")),
+    ]);
+
+    let sources = DatabaseRepository::new(&config)
+        .unwrap()
+        .get_property_sources("billing", "prod", "master")
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "database:application-default",
+            "database:billing-default",
+            "database:billing-prod"
+        ]
+    );
+    assert_eq!(sources[0].source["timeout"], "30");
+    assert_eq!(sources[2].source["db.url"], "postgres");
+}
+
+#[tokio::test]
+async fn converts_non_text_values() {
+    let (_dir, config) = database(&[
+        ("billing", "default", "master", "retries", 3i64.into()),
+        ("billing", "default", "master", "ratio", 0.5f64.into()),
+        (
+            "billing",
+            "default",
+            "master",
+            "unset",
+            rusqlite::types::Value::Null,
+        ),
+    ]);
+
+    let sources = DatabaseRepository::new(&config)
+        .unwrap()
+        .get_property_sources("billing", "default", "master")
+        .await
+        .unwrap();
+
+    assert_eq!(sources.len(), 1);
+    let source = &sources[0].source;
+    assert_eq!(source["retries"], json!(3));
+    assert_eq!(source["ratio"], json!(0.5));
+    assert_eq!(source["unset"], json!(null));
+}
+
+#[test]
+fn missing_databases_fail_on_startup() {
+    let dir = TempDir::new().unwrap();
+    let config = DatabaseConfig {
+        url: format!("sqlite:{}", dir.path().join("missing.db").display()),
+        query: None,
+        ca_path: None,
+    };
+
+    assert!(DatabaseRepository::new(&config).is_err());
+}
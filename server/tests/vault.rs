@@ -0,0 +1,237 @@
+//! Runs the Vault backend against a local HTTP stand-in for the KV v2 and
+//! AppRole endpoints.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, Uri},
+    routing::{get, post},
+};
+use config_server::{
+    config::{VaultAuthentication, VaultConfig},
+    repository::vault::VaultRepository,
+    utils::name::InvalidName,
+};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Default)]
+struct StandIn {
+    secrets: HashMap<String, Value>,
+    /// Token currently accepted by the secret endpoints
+    token: Mutex<String>,
+    logins: Mutex<usize>,
+    /// Raw request paths with the namespace header they carried
+    requests: Mutex<Vec<(String, Option<String>)>>,
+}
+
+async fn read_secret(
+    State(vault): State<Arc<StandIn>>,
+    Path(key): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    vault
+        .requests
+        .lock()
+        .unwrap()
+        .push((uri.path().to_string(), header("x-vault-namespace")));
+
+    if header("x-vault-token").as_deref() != Some(vault.token.lock().unwrap().as_str()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let data = vault.secrets.get(&key).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(
+        json!({ "data": { "data": data, "metadata": { "version": 1 } } }),
+    ))
+}
+
+async fn login(
+    State(vault): State<Arc<StandIn>>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, StatusCode> {
+    if body["role_id"] != "config-server" || body["secret_id"] != "s3cret" {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut logins = vault.logins.lock().unwrap();
+    *logins += 1;
+    let token = format!("login-{}", logins);
+    *vault.token.lock().unwrap() = token.clone();
+    Ok(Json(
+        json!({ "auth": { "client_token": token, "lease_duration": 3600 } }),
+    ))
+}
+
+async fn start(vault: Arc<StandIn>) -> String {
+    let app = Router::new()
+        .route("/v1/secret/data/{key}", get(read_secret))
+        .route("/v1/auth/approle/login", post(login))
+        .with_state(vault);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    address
+}
+
+fn config(address: String, authentication: VaultAuthentication) -> VaultConfig {
+    VaultConfig {
+        address,
+        backend: "secret".to_string(),
+        default_key: "application".to_string(),
+        profile_separator: ",".to_string(),
+        namespace: None,
+        authentication,
+        fail_fast: false,
+    }
+}
+
+fn token(token: &str) -> VaultAuthentication {
+    VaultAuthentication::Token {
+        token: token.to_string(),
+    }
+}
+
+fn stand_in(secrets: &[(&str, Value)]) -> Arc<StandIn> {
+    let vault = StandIn {
+        secrets: secrets
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect(),
+        ..Default::default()
+    };
+    *vault.token.lock().unwrap() = "root".to_string();
+    Arc::new(vault)
+}
+
+#[tokio::test]
+async fn reads_secrets_in_order_of_precedence() {
+    let vault = stand_in(&[
+        ("application", json!({ "db": { "user": "shared" } })),
+        ("billing", json!({ "db": { "password": "billing" } })),
+        (
+            "billing,prod",
+            json!({ "db": { "password": "billing-prod" } }),
+        ),
+    ]);
+    let mut config = config(start(vault.clone()).await, token("root"));
+    config.namespace = Some("team-a".to_string());
+
+    let sources = VaultRepository::new(&config)
+        .unwrap()
+        .get_property_sources("billing", "prod")
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "vault:secret/application",
+            "vault:secret/billing",
+            "vault:secret/billing,prod"
+        ]
+    );
+    assert_eq!(sources[0].source["db.user"], "shared");
+    assert_eq!(sources[2].source["db.password"], "billing-prod");
+
+    // `application,prod` does not exist and is skipped
+    let requests = vault.requests.lock().unwrap();
+    assert_eq!(requests.len(), 4);
+    assert!(
+        requests
+            .iter()
+            .all(|(_, namespace)| namespace.as_deref() == Some("team-a"))
+    );
+}
+
+#[tokio::test]
+async fn percent_encodes_secret_keys() {
+    let vault = stand_in(&[("my app", json!({ "key": "value" }))]);
+    let config = config(start(vault.clone()).await, token("root"));
+
+    let sources = VaultRepository::new(&config)
+        .unwrap()
+        .get_property_sources("my app", "default")
+        .await
+        .unwrap();
+
+    assert_eq!(sources.last().unwrap().name, "vault:secret/my app");
+    let requests = vault.requests.lock().unwrap();
+    assert!(
+        requests
+            .iter()
+            .any(|(path, _)| path == "/v1/secret/data/my%20app")
+    );
+}
+
+#[tokio::test]
+async fn rejects_names_that_escape_the_secret_path() {
+    let vault = stand_in(&[("payments", json!({ "key": "value" }))]);
+    let config = config(start(vault.clone()).await, token("root"));
+    let repository = VaultRepository::new(&config).unwrap();
+
+    for (application, profile) in [
+        ("billing/../payments", "default"),
+        ("billing", "../../payments"),
+        ("..", "default"),
+    ] {
+        let err = repository
+            .get_property_sources(application, profile)
+            .await
+            .unwrap_err();
+        assert!(err.is::<InvalidName>(), "{}", err);
+    }
+    assert!(vault.requests.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn logs_in_again_when_the_token_is_revoked() {
+    let vault = stand_in(&[("billing", json!({ "key": "value" }))]);
+    let config = config(
+        start(vault.clone()).await,
+        VaultAuthentication::AppRole {
+            role_id: "config-server".to_string(),
+            secret_id: Some("s3cret".to_string()),
+            path: "approle".to_string(),
+        },
+    );
+    let repository = VaultRepository::new(&config).unwrap();
+
+    let sources = repository
+        .get_property_sources("billing", "default")
+        .await
+        .unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(*vault.logins.lock().unwrap(), 1);
+
+    *vault.token.lock().unwrap() = "revoked".to_string();
+    let sources = repository
+        .get_property_sources("billing", "default")
+        .await
+        .unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(*vault.logins.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn reports_errors_for_rejected_tokens() {
+    let vault = stand_in(&[]);
+    let config = config(start(vault).await, token("wrong"));
+
+    let err = VaultRepository::new(&config)
+        .unwrap()
+        .get_property_sources("billing", "default")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("403"), "{}", err);
+}
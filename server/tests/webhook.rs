@@ -0,0 +1,152 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use config_server::utils::webhook::{GitHost, InvalidSignature, parse_push};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+const SECRET: &str = "webhook-secret";
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!(
+        "{}/tests/fixtures/webhook/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e))
+}
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            (
+                HeaderName::from_static(name),
+                HeaderValue::from_str(value).unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn sign(body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[test]
+fn github_push_lists_changed_files() {
+    let body = fixture("github-push.json");
+    let signature = format!("sha256={}", sign(&body));
+    let headers = headers(&[
+        ("x-github-event", "push"),
+        ("x-hub-signature-256", &signature),
+    ]);
+
+    let event = parse_push(&headers, &body, None, SECRET).unwrap().unwrap();
+
+    assert_eq!(event.host, GitHost::GitHub);
+    assert_eq!(
+        event.paths.unwrap(),
+        ["billing-dev.yml", "billing.yml", "legacy.properties"]
+    );
+}
+
+#[test]
+fn github_ping_is_ignored() {
+    let body = fixture("github-ping.json");
+    let signature = format!("sha256={}", sign(&body));
+    let headers = headers(&[
+        ("x-github-event", "ping"),
+        ("x-hub-signature-256", &signature),
+    ]);
+
+    assert!(parse_push(&headers, &body, None, SECRET).unwrap().is_none());
+}
+
+#[test]
+fn github_push_with_wrong_signature_is_rejected() {
+    let body = fixture("github-push.json");
+    let signature = format!("sha256={}", sign(b"something else"));
+    let headers = headers(&[
+        ("x-github-event", "push"),
+        ("x-hub-signature-256", &signature),
+    ]);
+
+    let err = parse_push(&headers, &body, None, SECRET).unwrap_err();
+    assert!(err.is::<InvalidSignature>());
+}
+
+#[test]
+fn github_push_without_signature_is_rejected() {
+    let body = fixture("github-push.json");
+    let headers = headers(&[("x-github-event", "push")]);
+
+    let err = parse_push(&headers, &body, Some(SECRET), SECRET).unwrap_err();
+    assert!(err.is::<InvalidSignature>());
+}
+
+#[test]
+fn gitlab_push_is_verified_by_token() {
+    let body = fixture("gitlab-push.json");
+
+    let valid = headers(&[("x-gitlab-event", "Push Hook"), ("x-gitlab-token", SECRET)]);
+    let event = parse_push(&valid, &body, None, SECRET).unwrap().unwrap();
+    assert_eq!(event.host, GitHost::GitLab);
+    assert_eq!(event.paths.unwrap(), ["application.yml", "orders.yml"]);
+
+    let invalid = headers(&[("x-gitlab-event", "Push Hook"), ("x-gitlab-token", "nope")]);
+    let err = parse_push(&invalid, &body, None, SECRET).unwrap_err();
+    assert!(err.is::<InvalidSignature>());
+}
+
+#[test]
+fn gitea_push_is_detected_before_github_headers() {
+    let body = fixture("gitea-push.json");
+    let signature = sign(&body);
+    let headers = headers(&[
+        ("x-gitea-event", "push"),
+        ("x-github-event", "push"),
+        ("x-gitea-signature", &signature),
+    ]);
+
+    let event = parse_push(&headers, &body, None, SECRET).unwrap().unwrap();
+
+    assert_eq!(event.host, GitHost::Gitea);
+    assert_eq!(event.paths.unwrap(), ["search/search-prod.yml"]);
+}
+
+#[test]
+fn bitbucket_push_accepts_signature_or_token() {
+    let body = fixture("bitbucket-push.json");
+
+    let signature = format!("sha256={}", sign(&body));
+    let signed = headers(&[
+        ("x-event-key", "repo:push"),
+        ("x-hub-signature", &signature),
+    ]);
+    let event = parse_push(&signed, &body, None, SECRET).unwrap().unwrap();
+    assert_eq!(event.host, GitHost::Bitbucket);
+    assert!(event.paths.is_none());
+
+    let unsigned = headers(&[("x-event-key", "repo:push")]);
+    assert!(
+        parse_push(&unsigned, &body, Some(SECRET), SECRET)
+            .unwrap()
+            .is_some()
+    );
+
+    let err = parse_push(&unsigned, &body, Some("nope"), SECRET).unwrap_err();
+    assert!(err.is::<InvalidSignature>());
+    let err = parse_push(&unsigned, &body, None, SECRET).unwrap_err();
+    assert!(err.is::<InvalidSignature>());
+}
+
+#[test]
+fn unknown_hosts_are_rejected() {
+    let body = fixture("github-push.json");
+
+    assert!(parse_push(&HeaderMap::new(), &body, Some(SECRET), SECRET).is_err());
+}
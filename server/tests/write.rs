@@ -0,0 +1,363 @@
+//! Applies edits to documents and writes them through the file and Git
+//! repositories.
+
+use config_server::{
+    config::{GitCredentials, GitOptions},
+    models::CommitAuthor,
+    repository::{
+        file::FileRepository,
+        git::GitRepository,
+        write::{Edit, InvalidWrite, VersionConflict, WriteMode},
+    },
+    utils::document::DocumentFormat,
+};
+use git2::{Repository, Signature};
+use serde_json::{Value, json};
+use std::{collections::BTreeMap, fs, path::Path};
+use tempfile::TempDir;
+
+fn edit(mode: WriteMode, properties: Value) -> Edit {
+    Edit {
+        mode,
+        properties: serde_json::from_value::<BTreeMap<String, Value>>(properties).unwrap(),
+    }
+}
+
+fn author() -> CommitAuthor {
+    CommitAuthor {
+        name: "Config Admin".to_string(),
+        email: "admin@example.com".to_string(),
+    }
+}
+
+#[test]
+fn merge_deletes_keys_set_to_null() {
+    let content = "db:\n  url: jdbc:h2:mem\n  hosts:\n    - a\n    - b\nname: billing\n";
+    let edit = edit(WriteMode::Merge, json!({ "db": null, "timeout": 30 }));
+
+    let updated = edit.apply(Some(content), DocumentFormat::Yaml).unwrap();
+
+    assert_eq!(updated, "name: billing\ntimeout: 30\n");
+}
+
+#[test]
+fn replace_drops_every_other_key() {
+    let content = "{\"a\": 1, \"b\": {\"c\": 2}}";
+    let edit = edit(WriteMode::Replace, json!({ "b": { "d": [true] } }));
+
+    let updated = edit.apply(Some(content), DocumentFormat::Json).unwrap();
+
+    assert_eq!(
+        serde_json::from_str::<Value>(&updated).unwrap(),
+        json!({ "b": { "d": [true] } })
+    );
+}
+
+#[test]
+fn yaml_is_reserialized_in_key_order() {
+    let content = "zone: \"eu #1\"\nurl: http://host/#anchor\nalpha: 1\n";
+    let edit = edit(WriteMode::Merge, json!({ "alpha": 2 }));
+
+    let updated = edit.apply(Some(content), DocumentFormat::Yaml).unwrap();
+
+    assert_eq!(
+        updated,
+        "alpha: 2\nurl: http://host/#anchor\nzone: 'eu #1'\n"
+    );
+}
+
+#[test]
+fn yaml_with_comments_is_not_rewritten() {
+    let edit = edit(WriteMode::Merge, json!({ "alpha": 2 }));
+
+    for content in [
+        "# Billing service\nalpha: 1\n",
+        "zone: eu # primary\nalpha: 1\n",
+        "zone: 'it''s' # quoted\nalpha: 1\n",
+    ] {
+        let err = edit.apply(Some(content), DocumentFormat::Yaml).unwrap_err();
+        assert!(err.is::<InvalidWrite>(), "{}", err);
+    }
+}
+
+#[test]
+fn properties_keep_comments_order_and_continuations() {
+    let content = "# Billing\nzone: eu\nlist = a,\\\n    b\nold value\n\ntimeout=10\n";
+    let edit = edit(
+        WriteMode::Merge,
+        json!({ "timeout": 20, "old": null, "new key": "x=y", "list": "c" }),
+    );
+
+    let updated = edit
+        .apply(Some(content), DocumentFormat::Properties)
+        .unwrap();
+
+    assert_eq!(
+        updated,
+        "# Billing\nzone: eu\nlist=c\n\ntimeout=20\nnew\\ key=x=y\n"
+    );
+}
+
+#[test]
+fn properties_round_trip_through_the_loader() {
+    let properties = json!({
+        "db.url": "jdbc:postgresql://db:5432/billing?ssl=true",
+        "greeting": "  hello # not a comment  ",
+        "path": "C:\\config\\",
+        "key:with=separators": "!value",
+        "unicode": "café",
+    });
+    let edit = edit(WriteMode::Replace, properties.clone());
+
+    let updated = edit.apply(None, DocumentFormat::Properties).unwrap();
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("billing.properties"), &updated).unwrap();
+    let config = FileRepository::new(dir.path().to_str().unwrap(), &[])
+        .unwrap()
+        .get_config("billing", "default", "master")
+        .unwrap();
+
+    let source = &config.property_sources.last().unwrap().source;
+    for (key, value) in properties.as_object().unwrap() {
+        assert_eq!(source.get(key), Some(value), "{} in {}", key, updated);
+    }
+}
+
+#[test]
+fn control_characters_are_rejected() {
+    for properties in [
+        json!({ "a": "x\nadmin.password=y" }),
+        json!({ "a\nadmin.password": "y" }),
+        json!({ "a": { "b\rc": "y" } }),
+    ] {
+        let err = edit(WriteMode::Merge, properties)
+            .apply(Some("a=1\n"), DocumentFormat::Properties)
+            .unwrap_err();
+        assert!(err.is::<InvalidWrite>(), "{}", err);
+    }
+}
+
+#[test]
+fn array_indexes_must_not_leave_gaps() {
+    let err = edit(WriteMode::Merge, json!({ "a[99999999999]": 1 }))
+        .apply(Some("a:\n  - 0\n"), DocumentFormat::Yaml)
+        .unwrap_err();
+    assert!(err.is::<InvalidWrite>(), "{}", err);
+
+    let updated = edit(WriteMode::Merge, json!({ "a[1]": 1 }))
+        .apply(Some("a:\n  - 0\n"), DocumentFormat::Yaml)
+        .unwrap();
+    assert_eq!(updated, "a:\n- 0\n- 1\n");
+}
+
+#[test]
+fn file_writes_go_to_the_search_directory_holding_the_document() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("billing")).unwrap();
+    fs::write(dir.path().join("billing.yml"), "source: root\n").unwrap();
+    fs::write(
+        dir.path().join("billing/billing.properties"),
+        "source=dir\n",
+    )
+    .unwrap();
+    let repository =
+        FileRepository::new(dir.path().to_str().unwrap(), &["{application}".to_string()]).unwrap();
+
+    let edit = edit(WriteMode::Merge, json!({ "timeout": 5 }));
+    repository
+        .write("billing", "default", "master", "billing", &edit)
+        .unwrap();
+    repository
+        .write("orders", "default", "master", "orders", &edit)
+        .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("billing/billing.properties")).unwrap(),
+        "source=dir\ntimeout=5\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("billing.yml")).unwrap(),
+        "source: root\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("orders.yml")).unwrap(),
+        "timeout: 5\n"
+    );
+}
+
+/// Creates a bare remote with `billing.yml` and `billing/billing.properties`
+/// on `master`, tagged `v1`.
+fn remote(dir: &Path) -> String {
+    let path = dir.join("remote.git");
+    let repo = Repository::init_bare(&path).unwrap();
+    let mut builder = repo.treebuilder(None).unwrap();
+    builder
+        .insert(
+            "billing.properties",
+            repo.blob(b"source=dir\n").unwrap(),
+            0o100644,
+        )
+        .unwrap();
+    let subtree = builder.write().unwrap();
+    let mut builder = repo.treebuilder(None).unwrap();
+    builder
+        .insert(
+            "billing.yml",
+            repo.blob(b"timeout: 10\n").unwrap(),
+            0o100644,
+        )
+        .unwrap();
+    builder.insert("billing", subtree, 0o040000).unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let signature = Signature::now("Config Admin", "admin@example.com").unwrap();
+    let commit = repo
+        .commit(
+            Some("refs/heads/master"),
+            &signature,
+            &signature,
+            "Initial",
+            &tree,
+            &[],
+        )
+        .unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+    repo.tag_lightweight("v1", &repo.find_object(commit, None).unwrap(), false)
+        .unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn clone(dir: &Path, uri: &str, search_paths: &[String]) -> GitRepository {
+    let options = GitOptions {
+        basedir: Some(dir.join("clone").to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    GitRepository::new(uri, &GitCredentials::default(), &options, search_paths).unwrap()
+}
+
+/// Reads a file from the tip of `master` in the remote.
+fn remote_file(uri: &str, path: &str) -> Option<String> {
+    let remote = Repository::open_bare(uri).unwrap();
+    let head = remote.find_reference("refs/heads/master").unwrap();
+    let tree = head.peel_to_commit().unwrap().tree().unwrap();
+    let entry = tree.get_path(Path::new(path)).ok()?;
+    let blob = entry.to_object(&remote).unwrap().peel_to_blob().unwrap();
+    Some(String::from_utf8(blob.content().to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn git_writes_commit_and_push() {
+    let dir = TempDir::new().unwrap();
+    let uri = remote(dir.path());
+    let repository = clone(dir.path(), &uri, &[]);
+    let version = repository
+        .get_config("billing", "default", "master")
+        .await
+        .unwrap()
+        .version
+        .unwrap();
+
+    let edit = edit(WriteMode::Merge, json!({ "timeout": 20 }));
+    let commit = repository
+        .write(
+            ("billing", "default", "master"),
+            Some(&version),
+            "billing",
+            &edit,
+            &author(),
+            Some("Raise timeout"),
+        )
+        .await
+        .unwrap();
+
+    let remote = Repository::open_bare(&uri).unwrap();
+    let head = remote.find_reference("refs/heads/master").unwrap();
+    let head = head.peel_to_commit().unwrap();
+    assert_eq!(head.id().to_string(), commit);
+    assert_eq!(head.message(), Some("Raise timeout"));
+    assert_eq!(head.author().name(), Some("Config Admin"));
+
+    let config = repository
+        .get_config("billing", "default", "master")
+        .await
+        .unwrap();
+    assert_eq!(config.version.as_deref(), Some(commit.as_str()));
+    assert_eq!(config.property_sources[0].source["timeout"], 20);
+}
+
+#[tokio::test]
+async fn git_writes_based_on_an_old_version_conflict() {
+    let dir = TempDir::new().unwrap();
+    let repository = clone(dir.path(), &remote(dir.path()), &[]);
+    let edit = edit(WriteMode::Merge, json!({ "timeout": 20 }));
+
+    let err = repository
+        .write(
+            ("billing", "default", "master"),
+            Some("0000000"),
+            "billing",
+            &edit,
+            &author(),
+            None,
+        )
+        .await
+        .unwrap_err();
+
+    let conflict = err.downcast_ref::<VersionConflict>().unwrap();
+    assert_eq!(conflict.expected, "0000000");
+}
+
+#[tokio::test]
+async fn git_writes_require_a_branch() {
+    let dir = TempDir::new().unwrap();
+    let repository = clone(dir.path(), &remote(dir.path()), &[]);
+    let edit = edit(WriteMode::Merge, json!({ "timeout": 20 }));
+
+    let err = repository
+        .write(
+            ("billing", "default", "v1"),
+            None,
+            "billing",
+            &edit,
+            &author(),
+            None,
+        )
+        .await
+        .unwrap_err();
+
+    assert!(err.is::<InvalidWrite>(), "{}", err);
+}
+
+#[tokio::test]
+async fn git_writes_go_to_the_search_directory_holding_the_document() {
+    let dir = TempDir::new().unwrap();
+    let uri = remote(dir.path());
+    let repository = clone(dir.path(), &uri, &["{application}".to_string()]);
+
+    let edit = edit(WriteMode::Merge, json!({ "timeout": 5 }));
+    for application in ["billing", "orders"] {
+        repository
+            .write(
+                (application, "default", "master"),
+                None,
+                application,
+                &edit,
+                &author(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        remote_file(&uri, "billing/billing.properties").as_deref(),
+        Some("source=dir\ntimeout=5\n")
+    );
+    assert_eq!(
+        remote_file(&uri, "billing.yml").as_deref(),
+        Some("timeout: 10\n")
+    );
+    assert_eq!(
+        remote_file(&uri, "orders.yml").as_deref(),
+        Some("timeout: 5\n")
+    );
+}